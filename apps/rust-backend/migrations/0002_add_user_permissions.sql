@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN permissions TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,12 @@
+-- One row per refresh token family, i.e. per signed-in device
+CREATE TABLE IF NOT EXISTS sessions (
+    id                TEXT PRIMARY KEY NOT NULL,
+    user_id           TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at        INTEGER NOT NULL,
+    last_refreshed_at INTEGER NOT NULL,
+    expires_at        INTEGER NOT NULL,
+    user_agent        TEXT,
+    ip                TEXT
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id ON sessions (user_id);
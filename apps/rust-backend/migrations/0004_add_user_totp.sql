@@ -0,0 +1,5 @@
+-- totp_secret is sealed with MFA_SYMMETRIC_KEY_BASE64; it is set but not yet enabled
+-- between enrollment and confirmation
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN totp_last_step INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+-- Single-use account recovery codes, stored as SHA-256 hex digests
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    user_id    TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash  TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    used_at    INTEGER,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,10 @@
+-- Confidential OAuth clients for the client credentials grant; secrets are Argon2 hashes
+CREATE TABLE IF NOT EXISTS oauth_clients (
+    id          TEXT PRIMARY KEY NOT NULL,
+    name        TEXT NOT NULL,
+    secret_hash TEXT NOT NULL,
+    scopes      TEXT NOT NULL DEFAULT '[]',
+    disabled    INTEGER NOT NULL DEFAULT 0,
+    created_at  INTEGER NOT NULL,
+    updated_at  INTEGER NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Set once the user proves control of the address through a verification link, or
+-- when the account was created from a provider-verified email
+ALTER TABLE users ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Id of the newest refresh token of the family; only that token may be rotated, so
+-- replays of rotated tokens are caught even after the in-memory revocation list is
+-- lost in a restart. Sessions from before this column have to sign in again.
+ALTER TABLE sessions ADD COLUMN refresh_jti TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,3 @@
+-- Bumped each time a magic link is redeemed. Links carry the value they were issued
+-- at, so each works once even after a restart clears the in-memory revocation list.
+ALTER TABLE users ADD COLUMN magic_link_generation INTEGER NOT NULL DEFAULT 0;
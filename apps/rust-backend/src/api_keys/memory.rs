@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::api_keys::{ApiKey, ApiKeyRepository};
+use crate::auth::error::AuthResult;
+
+/// Process-local repository; state is lost on restart. Meant for tests and quick demos.
+#[derive(Debug, Default)]
+pub struct InMemoryApiKeyRepository {
+    keys: RwLock<HashMap<String, ApiKey>>,
+}
+
+impl InMemoryApiKeyRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for InMemoryApiKeyRepository {
+    async fn create(&self, key: &ApiKey) -> AuthResult<()> {
+        let mut keys = self.keys.write().expect("api key repository lock");
+        keys.insert(key.id.clone(), key.clone());
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> AuthResult<Option<ApiKey>> {
+        let keys = self.keys.read().expect("api key repository lock");
+        Ok(keys.get(id).cloned())
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> AuthResult<Option<ApiKey>> {
+        let keys = self.keys.read().expect("api key repository lock");
+        Ok(keys.values().find(|k| k.key_hash == key_hash).cloned())
+    }
+
+    async fn list_for_user(&self, user_id: &str) -> AuthResult<Vec<ApiKey>> {
+        let keys = self.keys.read().expect("api key repository lock");
+        let mut listed: Vec<ApiKey> = keys.values().filter(|k| k.user_id == user_id).cloned().collect();
+        listed.sort_by_key(|k| k.created_at);
+        Ok(listed)
+    }
+
+    async fn touch(&self, id: &str, now: i64) -> AuthResult<()> {
+        let mut keys = self.keys.write().expect("api key repository lock");
+        if let Some(key) = keys.get_mut(id) {
+            key.last_used_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> AuthResult<()> {
+        let mut keys = self.keys.write().expect("api key repository lock");
+        keys.remove(id);
+        Ok(())
+    }
+}
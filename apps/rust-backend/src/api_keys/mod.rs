@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::permissions::has_permission;
+use crate::storage::StorageBackend;
+use crate::users::UserRepository;
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemoryApiKeyRepository;
+pub use sqlite::SqliteApiKeyRepository;
+
+/// Every key starts with this, so keys are recognisable in the `Authorization`
+/// header and in secret scanners.
+pub const KEY_PREFIX: &str = "ak_";
+
+/// Characters of the key kept in clear to tell keys apart in listings
+const DISPLAY_PREFIX_LEN: usize = 10;
+
+/// 256 bits per key. That is enough that a plain SHA-256 digest is safe to store
+/// and look up, unlike passwords, which need Argon2.
+const KEY_BYTES: usize = 32;
+
+/// `last_used_at` is written at most this often per key, not on every request
+const LAST_USED_GRANULARITY_SECS: i64 = 60;
+
+/// A long-lived credential acting for its user with a fixed set of scopes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// Start of the key, e.g. `ak_Xy3kQ9`, for recognising it in listings
+    pub prefix: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn create(&self, key: &ApiKey) -> AuthResult<()>;
+
+    async fn find(&self, id: &str) -> AuthResult<Option<ApiKey>>;
+
+    async fn find_by_hash(&self, key_hash: &str) -> AuthResult<Option<ApiKey>>;
+
+    async fn list_for_user(&self, user_id: &str) -> AuthResult<Vec<ApiKey>>;
+
+    async fn touch(&self, id: &str, now: i64) -> AuthResult<()>;
+
+    async fn delete(&self, id: &str) -> AuthResult<()>;
+}
+
+/// Builds the repository for the configured storage backend.
+pub fn repository(storage: &StorageBackend) -> web::Data<dyn ApiKeyRepository> {
+    let repo: Arc<dyn ApiKeyRepository> = match storage {
+        StorageBackend::Memory => Arc::new(InMemoryApiKeyRepository::new()),
+        StorageBackend::Sqlite(pool) => Arc::new(SqliteApiKeyRepository::new(pool.clone())),
+    };
+    web::Data::from(repo)
+}
+
+pub fn is_api_key(credential: &str) -> bool {
+    credential.starts_with(KEY_PREFIX)
+}
+
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Creates a key for `user_id`. Returns the stored record and the key itself,
+/// which is shown once and never stored.
+pub fn generate_key(user_id: &str, name: &str, scopes: Vec<String>, expires_at: i64) -> (ApiKey, String) {
+    let mut bytes = [0u8; KEY_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let key = format!("{KEY_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes));
+
+    let record = ApiKey {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        name: name.to_string(),
+        prefix: key[..DISPLAY_PREFIX_LEN].to_string(),
+        key_hash: hash_key(&key),
+        scopes,
+        created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        expires_at,
+        last_used_at: None,
+    };
+    (record, key)
+}
+
+/// Resolves an API key to a principal acting for its user. It carries no roles, so
+/// role-guarded endpoints turn it away and it is authorized by its scopes alone.
+/// Those only count while the user still holds them, so permission changes apply
+/// to existing keys.
+pub async fn authenticate_api_key(
+    keys: &dyn ApiKeyRepository,
+    users: &dyn UserRepository,
+    key: &str,
+    now: i64,
+) -> AuthResult<AuthenticatedUser> {
+    let record = keys.find_by_hash(&hash_key(key)).await?.ok_or(AuthError::InvalidApiKey)?;
+    if now >= record.expires_at {
+        return Err(AuthError::TokenExpired);
+    }
+    let user = match users.find_by_id(&record.user_id).await? {
+        Some(user) if !user.disabled => user,
+        _ => return Err(AuthError::InvalidApiKey),
+    };
+
+    if record
+        .last_used_at
+        .is_none_or(|last| now - last >= LAST_USED_GRANULARITY_SECS)
+    {
+        keys.touch(&record.id, now).await?;
+    }
+
+    let scope = record
+        .scopes
+        .into_iter()
+        .filter(|s| has_permission(&user.permissions, s))
+        .collect();
+    Ok(AuthenticatedUser {
+        user_id: user.id,
+        client_id: None,
+        roles: Vec::new(),
+        scope,
+        jti: record.id.clone(),
+        exp: record.expires_at,
+        session_id: None,
+        actor: None,
+        api_key_id: Some(record.id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::users::{InMemoryUserRepository, User};
+
+    #[actix_web::test]
+    async fn key_principal_has_scopes_but_no_roles() {
+        let users = InMemoryUserRepository::new();
+        let user = User::new(
+            "admin".into(),
+            "admin@example.com".into(),
+            "hash".into(),
+            vec!["admin".into()],
+            vec!["*".into()],
+        );
+        let user = users.create(user).await.unwrap();
+
+        let keys = InMemoryApiKeyRepository::new();
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let (record, key) = generate_key(&user.id, "ci", vec!["reports:read".into()], now + 3600);
+        keys.create(&record).await.unwrap();
+
+        let principal = authenticate_api_key(&keys, &users, &key, now).await.unwrap();
+        assert!(principal.roles.is_empty());
+        assert!(!principal.has_any_role(&["admin".to_string()]));
+        assert!(principal.has_permission("reports:read"));
+        assert!(!principal.has_permission("users:write"));
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::api_keys::{ApiKey, ApiKeyRepository};
+use crate::auth::error::AuthResult;
+use crate::storage::storage_error;
+
+const API_KEY_COLUMNS: &str =
+    "id, user_id, name, prefix, key_hash, scopes, created_at, expires_at, last_used_at";
+
+/// SQLite-backed repository over the pool opened by `storage::connect_sqlite`.
+#[derive(Debug, Clone)]
+pub struct SqliteApiKeyRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteApiKeyRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    async fn find_one(&self, column: &str, value: &str) -> AuthResult<Option<ApiKey>> {
+        let sql = format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE {column} = ?1");
+        let row = sqlx::query(&sql)
+            .bind(value)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        row.as_ref().map(row_to_api_key).transpose()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for SqliteApiKeyRepository {
+    async fn create(&self, key: &ApiKey) -> AuthResult<()> {
+        let scopes = serde_json::to_string(&key.scopes).map_err(storage_error)?;
+        sqlx::query(
+            "INSERT INTO api_keys (id, user_id, name, prefix, key_hash, scopes, created_at, expires_at, last_used_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )
+        .bind(&key.id)
+        .bind(&key.user_id)
+        .bind(&key.name)
+        .bind(&key.prefix)
+        .bind(&key.key_hash)
+        .bind(scopes)
+        .bind(key.created_at)
+        .bind(key.expires_at)
+        .bind(key.last_used_at)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> AuthResult<Option<ApiKey>> {
+        self.find_one("id", id).await
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> AuthResult<Option<ApiKey>> {
+        self.find_one("key_hash", key_hash).await
+    }
+
+    async fn list_for_user(&self, user_id: &str) -> AuthResult<Vec<ApiKey>> {
+        let sql = format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE user_id = ?1 ORDER BY created_at");
+        let rows = sqlx::query(&sql)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        rows.iter().map(row_to_api_key).collect()
+    }
+
+    async fn touch(&self, id: &str, now: i64) -> AuthResult<()> {
+        sqlx::query("UPDATE api_keys SET last_used_at = ?2 WHERE id = ?1")
+            .bind(id)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> AuthResult<()> {
+        sqlx::query("DELETE FROM api_keys WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+fn row_to_api_key(row: &SqliteRow) -> AuthResult<ApiKey> {
+    let scopes: String = row.try_get("scopes").map_err(storage_error)?;
+    Ok(ApiKey {
+        id: row.try_get("id").map_err(storage_error)?,
+        user_id: row.try_get("user_id").map_err(storage_error)?,
+        name: row.try_get("name").map_err(storage_error)?,
+        prefix: row.try_get("prefix").map_err(storage_error)?,
+        key_hash: row.try_get("key_hash").map_err(storage_error)?,
+        scopes: serde_json::from_str(&scopes).map_err(storage_error)?,
+        created_at: row.try_get("created_at").map_err(storage_error)?,
+        expires_at: row.try_get("expires_at").map_err(storage_error)?,
+        last_used_at: row.try_get("last_used_at").map_err(storage_error)?,
+    })
+}
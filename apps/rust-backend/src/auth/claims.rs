@@ -1,61 +1,96 @@
+use pasetors::claims::Claims;
 use serde::{Deserialize, Serialize};
-use time::{Duration, OffsetDateTime};
-use uuid::Uuid;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::permissions;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AccessClaims {
-    pub iss: String,
-    pub aud: String,
+pub struct RefreshClaims {
     pub sub: String,
     pub exp: i64,
     pub iat: i64,
-    pub nbf: i64,
     pub jti: String,
-    pub roles: Vec<String>,
-}
-
-impl AccessClaims {
-    pub fn new(iss: String, aud: String, sub: String, roles: Vec<String>, ttl_minutes: i64) -> Self {
-        let now = OffsetDateTime::now_utc();
-        let exp = now + Duration::minutes(ttl_minutes);
-        Self {
-            iss,
-            aud,
-            sub,
-            exp: exp.unix_timestamp(),
-            iat: now.unix_timestamp(),
-            nbf: now.unix_timestamp(),
-            jti: Uuid::new_v4().to_string(),
-            roles,
-        }
-    }
+    /// Shared by every token rotated from the same login
+    pub family_id: String,
 }
 
+/// The party actually behind a token issued by token exchange (RFC 8693 `act`).
+/// Its own `act` records an earlier link when delegation was chained.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RefreshClaims {
-    pub sub: String,
-    pub exp: i64,
-    pub iat: i64,
-    pub jti: String,
+pub struct Actor {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Box<Actor>>,
 }
 
-impl RefreshClaims {
-    pub fn new(sub: String, ttl_days: i64) -> Self {
-        let now = OffsetDateTime::now_utc();
-        let exp = now + Duration::days(ttl_days);
-        Self {
-            sub,
-            exp: exp.unix_timestamp(),
-            iat: now.unix_timestamp(),
-            jti: Uuid::new_v4().to_string(),
+impl Actor {
+    /// Human-readable name for logs, e.g. `user:42` or `client:worker`.
+    pub fn label(&self) -> String {
+        match (&self.sub, &self.client_id) {
+            (Some(sub), _) => format!("user:{sub}"),
+            (None, Some(client_id)) => format!("client:{client_id}"),
+            (None, None) => "unknown".into(),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthenticatedUser {
+    /// The `sub` claim. Empty for machine principals, which carry only `client_id`
     pub user_id: String,
+    /// OAuth client the token was issued to, from the `client_id` claim
+    pub client_id: Option<String>,
     pub roles: Vec<String>,
+    pub scope: Vec<String>,
     pub jti: String,
     pub exp: i64,
+    /// Session (refresh token family) the token was issued to, from the `sid` claim
+    pub session_id: Option<String>,
+    /// Who is really making the request when the token came from token exchange,
+    /// e.g. a support agent impersonating this user
+    pub actor: Option<Actor>,
+    /// Set when the request authenticated with an API key instead of a token
+    pub api_key_id: Option<String>,
+}
+
+impl AuthenticatedUser {
+    /// True for service tokens from the client credentials grant, which act for
+    /// no user.
+    pub fn is_machine(&self) -> bool {
+        self.user_id.is_empty()
+    }
+
+    /// True for tokens from token exchange, where someone else acts as this user.
+    pub fn is_delegated(&self) -> bool {
+        self.actor.is_some()
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_any_role<R: AsRef<str>>(&self, roles: &[R]) -> bool {
+        roles.iter().any(|r| self.has_role(r.as_ref()))
+    }
+
+    /// Checks `required` against the token's scope with wildcard matching
+    /// (see `auth::permissions`).
+    pub fn has_permission(&self, required: &str) -> bool {
+        permissions::has_permission(&self.scope, required)
+    }
+}
+
+/// Reads a registered time claim (RFC 3339 in PASETO) as a unix timestamp.
+pub fn timestamp_claim(payload: &Claims, name: &str) -> AuthResult<i64> {
+    payload
+        .get_claim(name)
+        .and_then(|v| v.as_str())
+        .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
+        .map(|t| t.unix_timestamp())
+        .ok_or_else(|| AuthError::MissingClaim(name.into()))
 }
@@ -0,0 +1,147 @@
+use pasetors::claims::{Claims, ClaimsValidationRules};
+use pasetors::local;
+use pasetors::token::{Local, UntrustedToken};
+use pasetors::version4::V4;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::auth::claims::timestamp_claim;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::revocation::email_token_revocations;
+use crate::config::get_config;
+use crate::users::User;
+
+/// What an emailed token is good for. Each purpose is sealed with its own implicit
+/// assertion, so a verification link cannot be redeemed as a password reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+    MagicLink,
+}
+
+impl EmailTokenPurpose {
+    fn assertion(self) -> &'static [u8] {
+        match self {
+            EmailTokenPurpose::VerifyEmail => b"email-verification",
+            EmailTokenPurpose::ResetPassword => b"password-reset",
+            EmailTokenPurpose::MagicLink => b"magic-link",
+        }
+    }
+
+    fn ttl_secs(self) -> i64 {
+        let cfg = get_config();
+        match self {
+            EmailTokenPurpose::VerifyEmail => cfg.email_verification_ttl_secs,
+            EmailTokenPurpose::ResetPassword => cfg.password_reset_ttl_secs,
+            EmailTokenPurpose::MagicLink => cfg.magic_link_ttl_secs,
+        }
+    }
+}
+
+/// Decrypted contents of an emailed token.
+#[derive(Debug, Clone)]
+pub struct EmailToken {
+    pub sub: String,
+    pub email: String,
+    pub jti: String,
+    pub exp: i64,
+    /// Fingerprint of the password hash a reset token was issued against
+    password: Option<String>,
+    /// `magic_link_generation` a magic link was issued at
+    generation: Option<i64>,
+}
+
+impl EmailToken {
+    /// Whether the token still describes `user`: same account, same address, for
+    /// resets the same password and for magic links the same generation. A reset
+    /// token dies with the password it was meant to replace, and a magic link with
+    /// the first redemption, even across restarts that clear the revocation list.
+    pub fn matches(&self, user: &User) -> bool {
+        self.sub == user.id
+            && self.email.eq_ignore_ascii_case(&user.email)
+            && self
+                .password
+                .as_deref()
+                .is_none_or(|fp| fp == password_fingerprint(&user.password_hash))
+            && self.generation.is_none_or(|g| g == user.magic_link_generation)
+    }
+}
+
+/// Issues a short-lived, single-use token for `purpose`, bound to the user's
+/// current email address.
+pub fn issue_email_token(purpose: EmailTokenPurpose, user: &User) -> AuthResult<String> {
+    let cfg = get_config();
+
+    let ttl = time::Duration::seconds(purpose.ttl_secs())
+        .try_into()
+        .map_err(|e| AuthError::Internal(format!("email token ttl: {e}")))?;
+    let mut claims = Claims::new_expires_in(&ttl)
+        .map_err(|e| AuthError::Internal(format!("claims new: {e}")))?;
+    claims
+        .subject(&user.id)
+        .map_err(|e| AuthError::Internal(format!("add sub: {e}")))?;
+    claims
+        .token_identifier(&uuid::Uuid::new_v4().to_string())
+        .map_err(|e| AuthError::Internal(format!("add jti: {e}")))?;
+    claims
+        .add_additional("email", json!(user.email))
+        .map_err(|e| AuthError::Internal(format!("add email: {e}")))?;
+    if purpose == EmailTokenPurpose::ResetPassword {
+        claims
+            .add_additional("pwd", json!(password_fingerprint(&user.password_hash)))
+            .map_err(|e| AuthError::Internal(format!("add pwd: {e}")))?;
+    }
+    if purpose == EmailTokenPurpose::MagicLink {
+        claims
+            .add_additional("gen", json!(user.magic_link_generation))
+            .map_err(|e| AuthError::Internal(format!("add gen: {e}")))?;
+    }
+
+    local::encrypt(&cfg.refresh_key, &claims, None, Some(purpose.assertion()))
+        .map_err(|e| AuthError::CryptoError(format!("encrypt error: {e}")))
+}
+
+/// Decrypts a token issued for `purpose`. Expired, redeemed and foreign tokens
+/// are all `EmailTokenInvalid`.
+pub fn verify_email_token(purpose: EmailTokenPurpose, token: &str) -> AuthResult<EmailToken> {
+    let cfg = get_config();
+
+    let untrusted = UntrustedToken::<Local, V4>::try_from(token)
+        .map_err(|_| AuthError::EmailTokenInvalid)?;
+    let trusted = local::decrypt(&cfg.refresh_key, &untrusted, &ClaimsValidationRules::new(), None, Some(purpose.assertion()))
+        .map_err(|_| AuthError::EmailTokenInvalid)?;
+    let payload = trusted.payload_claims().ok_or(AuthError::EmailTokenInvalid)?;
+
+    let string_claim = |name: &str| {
+        payload
+            .get_claim(name)
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+    };
+    let sub = string_claim("sub").ok_or(AuthError::EmailTokenInvalid)?;
+    let jti = string_claim("jti").ok_or(AuthError::EmailTokenInvalid)?;
+    let email = string_claim("email").ok_or(AuthError::EmailTokenInvalid)?;
+    let exp = timestamp_claim(payload, "exp")?;
+
+    if email_token_revocations().is_revoked(&jti) {
+        return Err(AuthError::EmailTokenInvalid);
+    }
+    let generation = payload.get_claim("gen").and_then(|v| v.as_i64());
+    Ok(EmailToken { sub, email, jti, exp, password: string_claim("pwd"), generation })
+}
+
+/// Marks the token redeemed; fails if another request got there first.
+pub fn consume_email_token(token: &EmailToken) -> AuthResult<()> {
+    if !email_token_revocations().revoke(&token.jti, token.exp) {
+        return Err(AuthError::EmailTokenInvalid);
+    }
+    Ok(())
+}
+
+fn password_fingerprint(password_hash: &str) -> String {
+    Sha256::digest(password_hash.as_bytes())
+        .iter()
+        .take(16)
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
@@ -1,5 +1,12 @@
+use actix_web::error::InternalError;
+use actix_web::http::header::{CACHE_CONTROL, RETRY_AFTER, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
 use thiserror::Error;
 
+use crate::middleware::request_id::current_request_id;
+
 #[derive(Debug, Error)]
 pub enum AuthError {
     #[error("invalid token format")]
@@ -8,12 +15,18 @@ pub enum AuthError {
     #[error("signature verification failed")]
     SignatureVerificationFailed,
 
+    #[error("unknown or retired signing key")]
+    UnknownKeyId,
+
     #[error("token expired")]
     TokenExpired,
 
     #[error("token not yet valid")]
     TokenNotYetValid,
 
+    #[error("token not issued for this audience")]
+    InvalidAudience,
+
     #[error("claim validation failed: {0}")]
     ClaimValidationFailed(String),
 
@@ -23,11 +36,288 @@ pub enum AuthError {
     #[error("cryptography error: {0}")]
     CryptoError(String),
 
+    #[error("token revoked")]
+    TokenRevoked,
+
+    #[error("refresh token reuse detected")]
+    RefreshTokenReused,
+
+    #[error("missing credentials")]
+    MissingCredentials,
+
+    #[error("invalid credentials")]
+    InvalidCredentials,
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("user not found")]
+    UserNotFound,
+
+    #[error("user already exists")]
+    UserAlreadyExists,
+
+    #[error("session not found")]
+    SessionNotFound,
+
+    #[error("invalid verification code")]
+    InvalidMfaCode,
+
+    #[error("mfa challenge invalid or expired")]
+    MfaChallengeInvalid,
+
+    #[error("mfa already enabled")]
+    MfaAlreadyEnabled,
+
+    #[error("mfa not enrolled")]
+    MfaNotEnrolled,
+
     #[error("refresh token invalid or expired")]
     RefreshTokenInvalid,
 
+    #[error("link invalid or expired")]
+    EmailTokenInvalid,
+
+    #[error("email address not verified")]
+    EmailNotVerified,
+
+    #[error("mail delivery failed: {0}")]
+    MailDelivery(String),
+
+    #[error("invalid api key")]
+    InvalidApiKey,
+
+    #[error("api key not found")]
+    ApiKeyNotFound,
+
+    #[error("client authentication failed")]
+    InvalidClient,
+
+    #[error("client not found")]
+    ClientNotFound,
+
+    #[error("client already exists")]
+    ClientAlreadyExists,
+
+    #[error("unsupported grant type: {0}")]
+    UnsupportedGrantType(String),
+
+    #[error("invalid scope: {0}")]
+    InvalidScope(String),
+
+    #[error("invalid id token: {0}")]
+    IdTokenInvalid(String),
+
+    #[error("identity provider not found")]
+    OidcProviderNotFound,
+
+    #[error("sign-in state invalid or expired")]
+    OidcStateInvalid,
+
+    #[error("no account is linked to this identity")]
+    OidcAccountNotLinked,
+
+    #[error("identity provider error: {0}")]
+    OidcProviderError(String),
+
+    #[error("too many requests")]
+    RateLimited { retry_after_secs: u64 },
+
+    #[error("storage error: {0}")]
+    Storage(String),
+
     #[error("internal error: {0}")]
     Internal(String),
 }
 
+impl AuthError {
+    /// Stable machine-readable code for clients to branch on. Never rename these.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidTokenFormat
+            | AuthError::SignatureVerificationFailed
+            | AuthError::UnknownKeyId
+            | AuthError::ClaimValidationFailed(_)
+            | AuthError::MissingClaim(_) => "invalid_token",
+            AuthError::TokenExpired => "token_expired",
+            AuthError::TokenNotYetValid => "token_not_yet_valid",
+            AuthError::InvalidAudience => "invalid_audience",
+            AuthError::TokenRevoked => "token_revoked",
+            AuthError::RefreshTokenInvalid => "invalid_refresh_token",
+            AuthError::RefreshTokenReused => "refresh_token_reused",
+            AuthError::MissingCredentials => "missing_credentials",
+            AuthError::InvalidCredentials => "invalid_credentials",
+            AuthError::Forbidden(_) => "forbidden",
+            AuthError::InvalidRequest(_) => "invalid_request",
+            AuthError::UserNotFound => "user_not_found",
+            AuthError::UserAlreadyExists => "user_already_exists",
+            AuthError::SessionNotFound => "session_not_found",
+            AuthError::InvalidMfaCode => "invalid_mfa_code",
+            AuthError::MfaChallengeInvalid => "invalid_mfa_challenge",
+            AuthError::MfaAlreadyEnabled => "mfa_already_enabled",
+            AuthError::MfaNotEnrolled => "mfa_not_enrolled",
+            AuthError::EmailTokenInvalid => "invalid_email_token",
+            AuthError::EmailNotVerified => "email_not_verified",
+            AuthError::MailDelivery(_) => "mail_delivery_failed",
+            AuthError::InvalidApiKey => "invalid_api_key",
+            AuthError::ApiKeyNotFound => "api_key_not_found",
+            AuthError::InvalidClient => "invalid_client",
+            AuthError::ClientNotFound => "client_not_found",
+            AuthError::ClientAlreadyExists => "client_already_exists",
+            AuthError::UnsupportedGrantType(_) => "unsupported_grant_type",
+            AuthError::InvalidScope(_) => "invalid_scope",
+            AuthError::IdTokenInvalid(_) => "invalid_id_token",
+            AuthError::OidcProviderNotFound => "oidc_provider_not_found",
+            AuthError::OidcStateInvalid => "invalid_oidc_state",
+            AuthError::OidcAccountNotLinked => "oidc_account_not_linked",
+            AuthError::OidcProviderError(_) => "oidc_provider_error",
+            AuthError::RateLimited { .. } => "rate_limited",
+            AuthError::CryptoError(_) | AuthError::Storage(_) | AuthError::Internal(_) => {
+                "internal_error"
+            }
+        }
+    }
+
+    /// Message safe to show to clients. Server-side details stay in the logs.
+    fn public_message(&self) -> String {
+        match self {
+            AuthError::CryptoError(_) | AuthError::Storage(_) | AuthError::Internal(_) => {
+                "internal error".into()
+            }
+            // Which claim failed is useful to an attacker probing token forgery, not to clients
+            AuthError::ClaimValidationFailed(_) | AuthError::MissingClaim(_) => {
+                "invalid token".into()
+            }
+            AuthError::IdTokenInvalid(_) => "invalid id token".into(),
+            AuthError::OidcProviderError(_) => "identity provider error".into(),
+            AuthError::MailDelivery(_) => "mail delivery failed".into(),
+            other => other.to_string(),
+        }
+    }
+
+    /// RFC 6750 error code for a rejected bearer request. `None` means no error
+    /// attribute: the request carried no credentials, or the failure is ours.
+    fn bearer_error_code(&self) -> Option<&'static str> {
+        match self.status_code() {
+            StatusCode::UNAUTHORIZED if matches!(self, AuthError::MissingCredentials) => None,
+            StatusCode::UNAUTHORIZED => Some("invalid_token"),
+            StatusCode::FORBIDDEN => Some("insufficient_scope"),
+            StatusCode::BAD_REQUEST => Some("invalid_request"),
+            _ => None,
+        }
+    }
+
+    /// `WWW-Authenticate` value for a rejected bearer request (RFC 6750 section 3).
+    pub fn bearer_challenge(&self) -> String {
+        let Some(code) = self.bearer_error_code() else {
+            return "Bearer".into();
+        };
+        // error_description may not contain quotes or backslashes
+        let description: String = self
+            .public_message()
+            .chars()
+            .filter(|c| matches!(*c, ' '..='~') && !matches!(*c, '"' | '\\'))
+            .collect();
+        format!("Bearer error=\"{code}\", error_description=\"{description}\"")
+    }
+
+    /// Renders the error with its bearer challenge. Use this instead of `into()`
+    /// wherever a bearer token (or its absence) is what got the request rejected.
+    pub fn into_bearer_error(self) -> actix_web::Error {
+        let mut response = self.error_response();
+        if !self.status_code().is_server_error()
+            && let Ok(value) = self.bearer_challenge().parse()
+        {
+            response.headers_mut().insert(WWW_AUTHENTICATE, value);
+        }
+        InternalError::from_response(self, response).into()
+    }
+
+    /// RFC 6749 section 5.2 error code, for the OAuth endpoints.
+    fn oauth_error_code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidClient => "invalid_client",
+            AuthError::UnsupportedGrantType(_) => "unsupported_grant_type",
+            AuthError::InvalidScope(_) => "invalid_scope",
+            AuthError::InvalidRequest(_) => "invalid_request",
+            AuthError::Forbidden(_) => "unauthorized_client",
+            _ if self.status_code().is_server_error() => "server_error",
+            _ => "invalid_grant",
+        }
+    }
+
+    /// Renders the error in the RFC 6749 shape OAuth clients expect instead of the
+    /// usual envelope. Use this in OAuth endpoints such as `/token`.
+    pub fn into_oauth_error(self) -> actix_web::Error {
+        let request_id = current_request_id();
+        if self.status_code().is_server_error() {
+            println!("[auth] request {} failed: {self}", request_id.as_deref().unwrap_or("-"));
+        }
+
+        let mut builder = HttpResponse::build(self.status_code());
+        builder.insert_header((CACHE_CONTROL, "no-store"));
+        // Section 5.2: failed client authentication answers with the scheme it expects
+        if matches!(self, AuthError::InvalidClient) {
+            builder.insert_header((WWW_AUTHENTICATE, "Basic realm=\"token\""));
+        }
+        let response = builder.json(json!({
+            "error": self.oauth_error_code(),
+            "error_description": self.public_message(),
+            "request_id": request_id,
+        }));
+        InternalError::from_response(self, response).into()
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthError::InvalidRequest(_)
+            | AuthError::OidcStateInvalid
+            | AuthError::EmailTokenInvalid
+            | AuthError::UnsupportedGrantType(_)
+            | AuthError::InvalidScope(_) => StatusCode::BAD_REQUEST,
+            AuthError::OidcAccountNotLinked | AuthError::EmailNotVerified => StatusCode::FORBIDDEN,
+            AuthError::UserNotFound
+            | AuthError::SessionNotFound
+            | AuthError::ClientNotFound
+            | AuthError::ApiKeyNotFound
+            | AuthError::OidcProviderNotFound => StatusCode::NOT_FOUND,
+            AuthError::UserAlreadyExists
+            | AuthError::ClientAlreadyExists
+            | AuthError::MfaAlreadyEnabled
+            | AuthError::MfaNotEnrolled => StatusCode::CONFLICT,
+            AuthError::CryptoError(_) | AuthError::Storage(_) | AuthError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AuthError::OidcProviderError(_) | AuthError::MailDelivery(_) => StatusCode::BAD_GATEWAY,
+            AuthError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let request_id = current_request_id();
+        if self.status_code().is_server_error() {
+            println!("[auth] request {} failed: {self}", request_id.as_deref().unwrap_or("-"));
+        }
+
+        let mut builder = HttpResponse::build(self.status_code());
+        if let AuthError::RateLimited { retry_after_secs } = self {
+            builder.insert_header((RETRY_AFTER, retry_after_secs.to_string()));
+        }
+        builder.json(json!({
+            "error": {
+                "code": self.code(),
+                "message": self.public_message(),
+                "request_id": request_id,
+            }
+        }))
+    }
+}
+
 pub type AuthResult<T> = Result<T, AuthError>;
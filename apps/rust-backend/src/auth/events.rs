@@ -0,0 +1,55 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::auth::claims::Actor;
+
+/// Security-relevant occurrences worth recording outside of normal request logs.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuthEvent {
+    /// A refresh token that had already been rotated was presented again.
+    RefreshTokenReuse {
+        sub: String,
+        family_id: String,
+        jti: String,
+    },
+    /// A recovery code was used to sign in; the user's other sessions were ended.
+    RecoveryCodeUsed {
+        sub: String,
+        remaining: usize,
+        revoked_sessions: usize,
+    },
+    /// A new set of recovery codes replaced the previous one.
+    RecoveryCodesRegenerated { sub: String },
+    /// The user proved control of their email address.
+    EmailVerified { sub: String, email: String },
+    /// The password was changed through an emailed reset link; every session of
+    /// the user was ended.
+    PasswordReset { sub: String, revoked_sessions: usize },
+    /// An administrator disabled the account; every session of the user was ended.
+    UserDisabled { sub: String, revoked_sessions: usize },
+    /// A token for `sub` was issued to `actor` by token exchange; `impersonation`
+    /// is set when the actor named the user instead of presenting their token.
+    TokenExchanged {
+        sub: String,
+        actor: Actor,
+        impersonation: bool,
+        scope: String,
+    },
+    /// An external OpenID Connect identity was linked to an account, which was
+    /// created for it when `created_user` is set.
+    OidcIdentityLinked {
+        sub: String,
+        provider: String,
+        created_user: bool,
+    },
+}
+
+/// Writes the event as a single JSON line so log shippers can pick it up.
+pub fn emit(event: AuthEvent) {
+    let line = serde_json::json!({
+        "ts": OffsetDateTime::now_utc().unix_timestamp(),
+        "auth_event": event,
+    });
+    println!("[auth] {line}");
+}
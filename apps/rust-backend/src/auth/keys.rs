@@ -0,0 +1,191 @@
+use std::fmt;
+
+use pasetors::keys::{AsymmetricPublicKey, AsymmetricSecretKey};
+use pasetors::paserk::{FormatAsPaserk, Id};
+use pasetors::version4::V4;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Lifecycle of a key in the access token keyring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    /// Signs new tokens. Exactly one key is active.
+    Active,
+    /// Published ahead of time so verifiers can cache it before it becomes active.
+    Upcoming,
+    /// No longer signs; still verifies tokens until `not_after`.
+    VerifyOnly,
+}
+
+pub struct KeyEntry {
+    /// PASERK `k4.pid` of the public key; carried in the token footer
+    pub kid: String,
+    pub status: KeyStatus,
+    pub secret: Option<AsymmetricSecretKey<V4>>,
+    pub public: AsymmetricPublicKey<V4>,
+    pub not_before: Option<i64>,
+    pub not_after: Option<i64>,
+}
+
+impl KeyEntry {
+    pub fn new(
+        status: KeyStatus,
+        secret: Option<AsymmetricSecretKey<V4>>,
+        public: AsymmetricPublicKey<V4>,
+        not_before: Option<i64>,
+        not_after: Option<i64>,
+    ) -> Self {
+        let mut kid = String::new();
+        Id::from(&public).fmt(&mut kid).expect("paserk id formatting");
+        Self { kid, status, secret, public, not_before, not_after }
+    }
+
+    /// The public half as a PASERK `k4.public` string.
+    pub fn public_paserk(&self) -> String {
+        let mut out = String::new();
+        self.public.fmt(&mut out).expect("paserk formatting");
+        out
+    }
+
+    /// Whether tokens carrying this key's `kid` are accepted at `now`: the key
+    /// has come into force and has not retired yet.
+    pub fn verifies_at(&self, now: i64) -> bool {
+        self.not_before.is_none_or(|t| now >= t) && !self.retired_at(now)
+    }
+
+    /// Whether `not_after` has passed, after which the key is no longer published.
+    pub fn retired_at(&self, now: i64) -> bool {
+        self.not_after.is_some_and(|t| now >= t)
+    }
+}
+
+impl fmt::Debug for KeyEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyEntry")
+            .field("kid", &self.kid)
+            .field("status", &self.status)
+            .field("has_secret", &self.secret.is_some())
+            .field("not_before", &self.not_before)
+            .field("not_after", &self.not_after)
+            .finish()
+    }
+}
+
+/// Ed25519 keys for v4.public access tokens: one active signer plus any number
+/// of upcoming and verify-only keys, looked up by `kid`.
+#[derive(Debug)]
+pub struct Keyring {
+    keys: Vec<KeyEntry>,
+    active: usize,
+}
+
+impl Keyring {
+    pub fn new(keys: Vec<KeyEntry>) -> Result<Self, String> {
+        let mut active = keys
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| k.status == KeyStatus::Active);
+        let (index, entry) = active.next().ok_or("keyring has no active key")?;
+        if active.next().is_some() {
+            return Err("keyring has more than one active key".into());
+        }
+        if entry.secret.is_none() {
+            return Err(format!("active key {} has no secret key", entry.kid));
+        }
+        // Tokens signed outside the window would be refused by every verifier
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        if let Some(not_before) = entry.not_before.filter(|t| now < *t) {
+            return Err(format!("active key {} is not valid before {not_before}", entry.kid));
+        }
+        if let Some(not_after) = entry.not_after.filter(|t| now >= *t) {
+            return Err(format!("active key {} expired at {not_after}", entry.kid));
+        }
+        Ok(Self { active: index, keys })
+    }
+
+    /// The key new access tokens are signed with.
+    pub fn signing_key(&self) -> &KeyEntry {
+        &self.keys[self.active]
+    }
+
+    /// The key matching `kid`, if it is still allowed to verify.
+    pub fn verifying_key(&self, kid: &str) -> Option<&KeyEntry> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        self.keys.iter().find(|k| k.kid == kid && k.verifies_at(now))
+    }
+
+    /// Keys other services should trust: the active key, upcoming keys (even
+    /// before their `not_before`, so caches have them in time) and verify-only
+    /// keys that have not retired yet.
+    pub fn published_keys(&self) -> impl Iterator<Item = &KeyEntry> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        self.keys.iter().filter(move |k| !k.retired_at(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pasetors::keys::{AsymmetricKeyPair, Generate};
+
+    use super::*;
+
+    fn key(status: KeyStatus, not_before: Option<i64>, not_after: Option<i64>) -> KeyEntry {
+        let kp = AsymmetricKeyPair::<V4>::generate().unwrap();
+        let secret = (status == KeyStatus::Active).then_some(kp.secret);
+        KeyEntry::new(status, secret, kp.public, not_before, not_after)
+    }
+
+    fn now() -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp()
+    }
+
+    #[test]
+    fn keys_are_selected_by_kid() {
+        let keyring = Keyring::new(vec![
+            key(KeyStatus::VerifyOnly, None, Some(now() + 3600)),
+            key(KeyStatus::Active, None, None),
+            key(KeyStatus::Upcoming, Some(now() + 3600), None),
+        ])
+        .unwrap();
+        let [retiring, active, upcoming] = [0, 1, 2].map(|i| keyring.keys[i].kid.clone());
+
+        assert_eq!(keyring.signing_key().kid, active);
+        assert_eq!(keyring.verifying_key(&active).unwrap().kid, active);
+        assert_eq!(keyring.verifying_key(&retiring).unwrap().kid, retiring);
+        // Published ahead of time, but not accepted before its not_before
+        assert!(keyring.verifying_key(&upcoming).is_none());
+        assert_eq!(keyring.published_keys().count(), 3);
+        assert!(keyring.verifying_key("k4.pid.unknown").is_none());
+    }
+
+    #[test]
+    fn retired_keys_stop_verifying_and_publishing() {
+        let keyring = Keyring::new(vec![
+            key(KeyStatus::Active, Some(now() - 60), Some(now() + 3600)),
+            key(KeyStatus::VerifyOnly, None, Some(now() - 1)),
+        ])
+        .unwrap();
+        let retired = keyring.keys[1].kid.clone();
+        assert!(keyring.verifying_key(&retired).is_none());
+        assert_eq!(keyring.published_keys().count(), 1);
+    }
+
+    #[test]
+    fn active_key_must_be_in_its_window() {
+        let early = Keyring::new(vec![key(KeyStatus::Active, Some(now() + 3600), None)]);
+        assert!(early.unwrap_err().contains("not valid before"));
+        let expired = Keyring::new(vec![key(KeyStatus::Active, None, Some(now() - 1))]);
+        assert!(expired.unwrap_err().contains("expired"));
+    }
+
+    #[test]
+    fn exactly_one_active_key_with_a_secret() {
+        assert!(Keyring::new(vec![key(KeyStatus::VerifyOnly, None, None)]).is_err());
+        let two = vec![key(KeyStatus::Active, None, None), key(KeyStatus::Active, None, None)];
+        assert!(Keyring::new(two).is_err());
+        let mut keyless = key(KeyStatus::Active, None, None);
+        keyless.secret = None;
+        assert!(Keyring::new(vec![keyless]).is_err());
+    }
+}
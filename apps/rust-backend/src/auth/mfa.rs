@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use pasetors::claims::{Claims, ClaimsValidationRules};
+use pasetors::local;
+use pasetors::token::{Local, UntrustedToken};
+use pasetors::version4::{LocalToken, V4};
+
+use crate::auth::claims::timestamp_claim;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::revocation::mfa_challenge_revocations;
+use crate::auth::totp;
+use crate::config::get_config;
+
+/// Wrong codes tolerated per challenge before the password step must be repeated
+const MAX_CHALLENGE_ATTEMPTS: u32 = 5;
+
+/// Implicit assertion separating challenge tokens from anything else sealed with
+/// the MFA key
+const CHALLENGE_ASSERTION: &[u8] = b"mfa-challenge";
+
+/// Proof that the password step succeeded, exchanged for tokens at `/mfa/verify`.
+#[derive(Debug, Clone)]
+pub struct MfaChallenge {
+    pub sub: String,
+    pub jti: String,
+    pub exp: i64,
+}
+
+/// Encrypts a TOTP secret for storage. The user id is bound in as the implicit
+/// assertion, so a sealed secret copied onto another account will not open.
+pub fn seal_secret(user_id: &str, secret: &[u8]) -> AuthResult<String> {
+    let cfg = get_config();
+    let encoded = totp::base32_encode(secret);
+    LocalToken::encrypt(&cfg.mfa_key, encoded.as_bytes(), None, Some(secret_assertion(user_id).as_bytes()))
+        .map_err(|e| AuthError::CryptoError(format!("seal totp secret: {e}")))
+}
+
+pub fn open_secret(user_id: &str, sealed: &str) -> AuthResult<Vec<u8>> {
+    let cfg = get_config();
+    let untrusted = UntrustedToken::<Local, V4>::try_from(sealed)
+        .map_err(|e| AuthError::CryptoError(format!("sealed totp secret: {e}")))?;
+    let trusted = LocalToken::decrypt(&cfg.mfa_key, &untrusted, None, Some(secret_assertion(user_id).as_bytes()))
+        .map_err(|e| AuthError::CryptoError(format!("open totp secret: {e}")))?;
+    totp::base32_decode(trusted.payload())
+        .ok_or_else(|| AuthError::CryptoError("sealed totp secret is not base32".into()))
+}
+
+fn secret_assertion(user_id: &str) -> String {
+    format!("totp-secret:{user_id}")
+}
+
+pub fn issue_mfa_challenge(sub: &str) -> AuthResult<(String, MfaChallenge)> {
+    let cfg = get_config();
+
+    let ttl = time::Duration::seconds(cfg.mfa_challenge_ttl_secs)
+        .try_into()
+        .map_err(|e| AuthError::Internal(format!("mfa challenge ttl: {e}")))?;
+    let mut claims = Claims::new_expires_in(&ttl)
+        .map_err(|e| AuthError::Internal(format!("claims new: {e}")))?;
+    claims
+        .subject(sub)
+        .map_err(|e| AuthError::Internal(format!("add sub: {e}")))?;
+    claims
+        .token_identifier(&uuid::Uuid::new_v4().to_string())
+        .map_err(|e| AuthError::Internal(format!("add jti: {e}")))?;
+
+    let token = local::encrypt(&cfg.mfa_key, &claims, None, Some(CHALLENGE_ASSERTION))
+        .map_err(|e| AuthError::CryptoError(format!("encrypt error: {e}")))?;
+    let challenge = decode_mfa_challenge(&token)?;
+    Ok((token, challenge))
+}
+
+/// Decrypts a challenge token. Consumed or exhausted challenges are refused.
+pub fn verify_mfa_challenge(token: &str) -> AuthResult<MfaChallenge> {
+    let challenge = decode_mfa_challenge(token)?;
+    if mfa_challenge_revocations().is_revoked(&challenge.jti) {
+        return Err(AuthError::MfaChallengeInvalid);
+    }
+    Ok(challenge)
+}
+
+/// Marks the challenge used so it cannot be exchanged for tokens twice.
+pub fn consume_mfa_challenge(challenge: &MfaChallenge) -> AuthResult<()> {
+    if !mfa_challenge_revocations().revoke(&challenge.jti, challenge.exp) {
+        return Err(AuthError::MfaChallengeInvalid);
+    }
+    Ok(())
+}
+
+/// Counts a wrong code against the challenge and burns it once the attempts run out.
+pub fn record_failed_attempt(challenge: &MfaChallenge) {
+    let mut attempts = challenge_attempts().lock().expect("mfa attempts lock");
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    attempts.retain(|_, (_, exp)| *exp > now);
+
+    let (count, _) = attempts.entry(challenge.jti.clone()).or_insert((0, challenge.exp));
+    *count += 1;
+    if *count >= MAX_CHALLENGE_ATTEMPTS {
+        mfa_challenge_revocations().revoke(&challenge.jti, challenge.exp);
+    }
+}
+
+fn decode_mfa_challenge(token: &str) -> AuthResult<MfaChallenge> {
+    let cfg = get_config();
+
+    let untrusted = UntrustedToken::<Local, V4>::try_from(token)
+        .map_err(|_| AuthError::MfaChallengeInvalid)?;
+    let trusted = local::decrypt(&cfg.mfa_key, &untrusted, &ClaimsValidationRules::new(), None, Some(CHALLENGE_ASSERTION))
+        .map_err(|_| AuthError::MfaChallengeInvalid)?;
+    let payload = trusted.payload_claims().ok_or(AuthError::MfaChallengeInvalid)?;
+
+    let sub = payload
+        .get_claim("sub")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .ok_or(AuthError::MfaChallengeInvalid)?;
+    let jti = payload
+        .get_claim("jti")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .ok_or(AuthError::MfaChallengeInvalid)?;
+    let exp = timestamp_claim(payload, "exp")?;
+
+    Ok(MfaChallenge { sub, jti, exp })
+}
+
+fn challenge_attempts() -> &'static Mutex<HashMap<String, (u32, i64)>> {
+    static ATTEMPTS: OnceLock<Mutex<HashMap<String, (u32, i64)>>> = OnceLock::new();
+    ATTEMPTS.get_or_init(Default::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_locks_out_after_max_attempts() {
+        let (token, challenge) = issue_mfa_challenge("user-1").unwrap();
+
+        for _ in 1..MAX_CHALLENGE_ATTEMPTS {
+            record_failed_attempt(&challenge);
+            assert!(verify_mfa_challenge(&token).is_ok());
+        }
+        record_failed_attempt(&challenge);
+        assert!(matches!(verify_mfa_challenge(&token), Err(AuthError::MfaChallengeInvalid)));
+        assert!(consume_mfa_challenge(&challenge).is_err());
+    }
+
+    #[test]
+    fn challenge_is_single_use() {
+        let (token, challenge) = issue_mfa_challenge("user-2").unwrap();
+        assert_eq!(verify_mfa_challenge(&token).unwrap().sub, "user-2");
+
+        consume_mfa_challenge(&challenge).unwrap();
+        assert!(matches!(verify_mfa_challenge(&token), Err(AuthError::MfaChallengeInvalid)));
+        assert!(consume_mfa_challenge(&challenge).is_err());
+    }
+
+    #[test]
+    fn sealed_secret_opens_only_for_its_user() {
+        let secret = totp::generate_secret();
+        let sealed = seal_secret("user-3", &secret).unwrap();
+
+        assert_eq!(open_secret("user-3", &sealed).unwrap(), secret);
+        assert!(open_secret("user-4", &sealed).is_err());
+    }
+}
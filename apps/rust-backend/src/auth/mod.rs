@@ -2,3 +2,13 @@ pub mod claims;
 pub mod token;
 pub mod refresh;
 pub mod error;
+pub mod password;
+pub mod revocation;
+pub mod events;
+pub mod keys;
+pub mod key_material;
+pub mod permissions;
+pub mod validation;
+pub mod totp;
+pub mod mfa;
+pub mod email_token;
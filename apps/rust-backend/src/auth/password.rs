@@ -0,0 +1,45 @@
+use std::sync::OnceLock;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+use crate::auth::error::{AuthError, AuthResult};
+
+/// Shortest password accepted when one is chosen at signup or reset
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// Rejects passwords too short to be worth hashing.
+pub fn check_password_policy(password: &str) -> AuthResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AuthError::InvalidRequest(format!(
+            "password must be at least {MIN_PASSWORD_LEN} characters"
+        )));
+    }
+    Ok(())
+}
+
+// Argon2::default() is Argon2id v19 with the OWASP-recommended parameters
+pub fn hash_password(password: &str) -> AuthResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| AuthError::CryptoError(format!("password hash error: {e}")))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> AuthResult<bool> {
+    let parsed = PasswordHash::new(password_hash)
+        .map_err(|e| AuthError::CryptoError(format!("stored hash error: {e}")))?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok())
+}
+
+/// Burns the same amount of work as a real verification so that unknown
+/// accounts can't be told apart from wrong passwords by response time.
+pub fn verify_dummy_password(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| hash_password("dummy-password").unwrap_or_default());
+    let _ = verify_password(password, hash);
+}
@@ -0,0 +1,77 @@
+//! Permission strings are `:`-separated paths such as `orders:read` or
+//! `billing:invoices:write`. A granted permission may use `*` for one segment
+//! (`orders:*:read`) or as its last segment for a whole subtree (`billing:*`
+//! covers `billing:read` and `billing:invoices:write`). `*` alone grants everything.
+
+/// Whether the `granted` permission satisfies the `required` one.
+pub fn permission_matches(granted: &str, required: &str) -> bool {
+    let mut granted_parts = granted.split(':').peekable();
+    let mut required_parts = required.split(':');
+
+    while let Some(g) = granted_parts.next() {
+        let Some(r) = required_parts.next() else {
+            // Required is shorter than the grant: `orders:read` does not cover `orders`
+            return false;
+        };
+        if g == "*" {
+            if granted_parts.peek().is_none() {
+                return true;
+            }
+            continue;
+        }
+        if g != r {
+            return false;
+        }
+    }
+
+    required_parts.next().is_none()
+}
+
+pub fn has_permission<S: AsRef<str>>(granted: &[S], required: &str) -> bool {
+    granted.iter().any(|g| permission_matches(g.as_ref(), required))
+}
+
+/// Joins permissions into an OAuth-style space-delimited `scope` string.
+pub fn to_scope(permissions: &[String]) -> String {
+    permissions.join(" ")
+}
+
+pub fn from_scope(scope: &str) -> Vec<String> {
+    scope.split_whitespace().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_alone_grants_everything() {
+        assert!(permission_matches("*", "orders"));
+        assert!(permission_matches("*", "billing:invoices:write"));
+    }
+
+    #[test]
+    fn trailing_star_covers_the_subtree() {
+        assert!(permission_matches("billing:*", "billing:read"));
+        assert!(permission_matches("billing:*", "billing:invoices:write"));
+        assert!(!permission_matches("billing:*", "orders:read"));
+        // The subtree, not its root
+        assert!(!permission_matches("billing:*", "billing"));
+    }
+
+    #[test]
+    fn middle_star_stands_for_one_segment() {
+        assert!(permission_matches("orders:*:read", "orders:eu:read"));
+        assert!(!permission_matches("orders:*:read", "orders:eu:write"));
+        assert!(!permission_matches("orders:*:read", "orders:eu:archive:read"));
+        assert!(!permission_matches("orders:*:read", "orders:eu"));
+    }
+
+    #[test]
+    fn segments_match_exactly() {
+        assert!(permission_matches("orders:read", "orders:read"));
+        assert!(!permission_matches("orders:read", "orders"));
+        assert!(!permission_matches("orders", "orders:read"));
+        assert!(!permission_matches("orders:read", "orders:readall"));
+    }
+}
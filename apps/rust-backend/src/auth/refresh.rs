@@ -1,52 +1,64 @@
-use crate::auth::claims::RefreshClaims;
+use crate::auth::claims::{timestamp_claim, RefreshClaims};
 use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::events::{self, AuthEvent};
+use crate::auth::revocation::{refresh_family_revocations, refresh_revocations};
 use crate::config::get_config;
 
 use pasetors::claims::{Claims, ClaimsValidationRules};
-use pasetors::keys::SymmetricKey;
-use pasetors::local::{self, Local, version4::V4};
-use pasetors::token::{TrustedToken, UntrustedToken};
+use pasetors::local;
+use pasetors::token::{Local, UntrustedToken};
+use pasetors::version4::V4;
 use serde_json::json;
 use std::convert::TryFrom;
 use time::{Duration, OffsetDateTime};
 
-pub fn issue_refresh_token(sub: &str) -> AuthResult<String> {
-    let cfg = get_config();
-
-    // Build claims for refresh token
-    let mut claims = Claims::new();
-    let now = OffsetDateTime::now_utc();
-    let exp = now + Duration::days(cfg.refresh_ttl_days);
-    claims.add_iat(now.unix_timestamp());
-    claims.add_exp(exp.unix_timestamp());
+/// Issues the first refresh token of a new family (i.e. a fresh login). The
+/// returned claims carry the family id, which doubles as the session id.
+pub fn issue_refresh_token(sub: &str) -> AuthResult<(String, RefreshClaims)> {
+    let token = issue_refresh_token_in_family(sub, &uuid::Uuid::new_v4().to_string())?;
+    let claims = decode_refresh_token(&token)?;
+    Ok((token, claims))
+}
 
-    // Minimal additional claims
-    claims.add_additional("sub", json!(sub));
-    claims.add_additional("jti", json!(uuid::Uuid::new_v4().to_string()));
+/// Issues a refresh token belonging to an existing family; used by rotation so
+/// every descendant of one login shares the same `fid`.
+fn issue_refresh_token_in_family(sub: &str, family_id: &str) -> AuthResult<String> {
+    let cfg = get_config();
 
-    // Symmetric key
-    let sk = SymmetricKey::<V4>::try_from(cfg.refresh_key.as_slice())
-        .map_err(|e| AuthError::CryptoError(format!("refresh key error: {e}")))?;
+    // Build claims for refresh token; iat/nbf/exp are set by pasetors
+    let ttl = Duration::days(cfg.refresh_ttl_days)
+        .try_into()
+        .map_err(|e| AuthError::Internal(format!("refresh ttl: {e}")))?;
+    let mut claims = Claims::new_expires_in(&ttl)
+        .map_err(|e| AuthError::Internal(format!("claims new: {e}")))?;
+
+    claims
+        .subject(sub)
+        .map_err(|e| AuthError::Internal(format!("add sub: {e}")))?;
+    claims
+        .token_identifier(&uuid::Uuid::new_v4().to_string())
+        .map_err(|e| AuthError::Internal(format!("add jti: {e}")))?;
+    claims
+        .add_additional("fid", json!(family_id))
+        .map_err(|e| AuthError::Internal(format!("add fid: {e}")))?;
 
     // Encrypt (no footer/implicit assertion)
-    let token = local::encrypt(&sk, &claims, None, None)
+    let token = local::encrypt(&cfg.refresh_key, &claims, None, None)
         .map_err(|e| AuthError::CryptoError(format!("encrypt error: {e}")))?;
 
     Ok(token)
 }
 
-pub fn verify_refresh_token(token: &str) -> AuthResult<RefreshClaims> {
+/// Decrypts and validates a refresh token without consulting revocation state.
+pub fn decode_refresh_token(token: &str) -> AuthResult<RefreshClaims> {
     let cfg = get_config();
 
-    let sk = SymmetricKey::<V4>::try_from(cfg.refresh_key.as_slice())
-        .map_err(|e| AuthError::CryptoError(format!("refresh key error: {e}")))?;
-
     let untrusted = UntrustedToken::<Local, V4>::try_from(token)
         .map_err(|_| AuthError::InvalidTokenFormat)?;
 
     let rules = ClaimsValidationRules::new();
 
-    let trusted: TrustedToken<Local, V4> = local::decrypt(&sk, &untrusted, &rules, None)
+    let trusted = local::decrypt(&cfg.refresh_key, &untrusted, &rules, None, None)
         .map_err(|_| AuthError::RefreshTokenInvalid)?;
 
     let payload = trusted
@@ -58,28 +70,66 @@ pub fn verify_refresh_token(token: &str) -> AuthResult<RefreshClaims> {
         .and_then(|v| v.as_str().map(|s| s.to_string()))
         .ok_or_else(|| AuthError::MissingClaim("sub".into()))?;
 
+    // Without a jti the token could never be revoked, so refuse it outright
     let jti = payload
         .get_claim("jti")
         .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .unwrap_or_default();
+        .ok_or_else(|| AuthError::MissingClaim("jti".into()))?;
 
-    let exp = payload
-        .get_claim("exp")
-        .and_then(|v| v.as_i64())
-        .ok_or_else(|| AuthError::MissingClaim("exp".into()))?;
+    let family_id = payload
+        .get_claim("fid")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .ok_or_else(|| AuthError::MissingClaim("fid".into()))?;
 
-    let iat = payload
-        .get_claim("iat")
-        .and_then(|v| v.as_i64())
-        .unwrap_or_default();
+    let exp = timestamp_claim(payload, "exp")?;
+    let iat = timestamp_claim(payload, "iat").unwrap_or_default();
 
-    Ok(RefreshClaims { sub, exp, iat, jti })
+    Ok(RefreshClaims { sub, exp, iat, jti, family_id })
 }
 
-pub fn rotate_refresh_token(old_token: &str) -> AuthResult<(String, RefreshClaims)> {
-    let old = verify_refresh_token(old_token)?;
-    // Create new refresh token with same subject
-    let new_token = issue_refresh_token(&old.sub)?;
-    let new_claims = verify_refresh_token(&new_token)?;
+pub fn verify_refresh_token(token: &str) -> AuthResult<RefreshClaims> {
+    let claims = decode_refresh_token(token)?;
+    if refresh_revocations().is_revoked(&claims.jti)
+        || refresh_family_revocations().is_revoked(&claims.family_id)
+    {
+        return Err(AuthError::TokenRevoked);
+    }
+    Ok(claims)
+}
+
+/// Revokes every token descended from the same login as `family_id`.
+pub fn revoke_refresh_family(family_id: &str) {
+    // No member of the family can outlive a token issued right now
+    let cfg = get_config();
+    let until = OffsetDateTime::now_utc() + Duration::days(cfg.refresh_ttl_days);
+    refresh_family_revocations().revoke(family_id, until.unix_timestamp());
+}
+
+/// Handles a refresh token presented after it was already rotated: per the OAuth
+/// 2.0 Security BCP, someone is replaying it, so the whole family is killed.
+pub fn refresh_token_reused(old: &RefreshClaims) -> AuthError {
+    revoke_refresh_family(&old.family_id);
+    events::emit(AuthEvent::RefreshTokenReuse {
+        sub: old.sub.clone(),
+        family_id: old.family_id.clone(),
+        jti: old.jti.clone(),
+    });
+    AuthError::RefreshTokenReused
+}
+
+/// Consumes the decoded token `old` and issues its successor in the same family.
+pub fn rotate_refresh_token(old: &RefreshClaims) -> AuthResult<(String, RefreshClaims)> {
+    if refresh_family_revocations().is_revoked(&old.family_id) {
+        return Err(AuthError::TokenRevoked);
+    }
+
+    // Consume the old token first; if it was already consumed, it is being replayed
+    if !refresh_revocations().revoke(&old.jti, old.exp) {
+        return Err(refresh_token_reused(old));
+    }
+
+    // Create new refresh token with same subject and family
+    let new_token = issue_refresh_token_in_family(&old.sub, &old.family_id)?;
+    let new_claims = decode_refresh_token(&new_token)?;
     Ok((new_token, new_claims))
 }
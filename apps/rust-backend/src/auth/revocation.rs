@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use time::OffsetDateTime;
+
+// How often revoke() sweeps out entries whose token has expired anyway
+const PRUNE_INTERVAL_SECS: i64 = 60;
+
+/// Set of revoked token ids (`jti`), each remembered only until the token's own
+/// `exp`; past that point the token is rejected by expiry and the entry is dropped.
+#[derive(Debug, Default)]
+pub struct RevocationStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, i64>,
+    last_pruned: i64,
+}
+
+impl RevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks `jti` revoked until `exp`. Returns `false` if it was already revoked,
+    /// which lets callers treat revocation as an atomic "consume".
+    pub fn revoke(&self, jti: &str, exp: i64) -> bool {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut inner = self.inner.lock().expect("revocation store lock");
+
+        if now - inner.last_pruned >= PRUNE_INTERVAL_SECS {
+            inner.entries.retain(|_, exp| *exp > now);
+            inner.last_pruned = now;
+        }
+
+        match inner.entries.get(jti) {
+            Some(existing) if *existing > now => false,
+            _ => {
+                inner.entries.insert(jti.to_string(), exp);
+                true
+            }
+        }
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let inner = self.inner.lock().expect("revocation store lock");
+        inner.entries.get(jti).is_some_and(|exp| *exp > now)
+    }
+}
+
+/// Ids of access tokens per user, for tokens that belong to no session and so can't
+/// be cut off through `sid`. Entries are kept until the token's `exp`.
+#[derive(Debug, Default)]
+pub struct UserTokenIndex {
+    inner: Mutex<IndexInner>,
+}
+
+#[derive(Debug, Default)]
+struct IndexInner {
+    entries: HashMap<String, Vec<(String, i64)>>,
+    last_pruned: i64,
+}
+
+impl UserTokenIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, user_id: &str, jti: &str, exp: i64) {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut inner = self.inner.lock().expect("user token index lock");
+
+        if now - inner.last_pruned >= PRUNE_INTERVAL_SECS {
+            inner.entries.retain(|_, tokens| {
+                tokens.retain(|(_, exp)| *exp > now);
+                !tokens.is_empty()
+            });
+            inner.last_pruned = now;
+        }
+
+        inner
+            .entries
+            .entry(user_id.to_string())
+            .or_default()
+            .push((jti.to_string(), exp));
+    }
+
+    /// Removes and returns the unexpired `(jti, exp)` entries of `user_id`.
+    pub fn take(&self, user_id: &str) -> Vec<(String, i64)> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut inner = self.inner.lock().expect("user token index lock");
+        let mut tokens = inner.entries.remove(user_id).unwrap_or_default();
+        tokens.retain(|(_, exp)| *exp > now);
+        tokens
+    }
+}
+
+static REFRESH_REVOCATIONS: OnceLock<RevocationStore> = OnceLock::new();
+static REFRESH_FAMILY_REVOCATIONS: OnceLock<RevocationStore> = OnceLock::new();
+static ACCESS_REVOCATIONS: OnceLock<RevocationStore> = OnceLock::new();
+static MFA_CHALLENGE_REVOCATIONS: OnceLock<RevocationStore> = OnceLock::new();
+static EMAIL_TOKEN_REVOCATIONS: OnceLock<RevocationStore> = OnceLock::new();
+static SESSIONLESS_ACCESS_TOKENS: OnceLock<UserTokenIndex> = OnceLock::new();
+
+/// Revoked refresh token ids, shared by rotation, logout and verification.
+pub fn refresh_revocations() -> &'static RevocationStore {
+    REFRESH_REVOCATIONS.get_or_init(RevocationStore::new)
+}
+
+/// Revoked refresh token families (`fid`), populated by reuse detection.
+pub fn refresh_family_revocations() -> &'static RevocationStore {
+    REFRESH_FAMILY_REVOCATIONS.get_or_init(RevocationStore::new)
+}
+
+/// Access token ids denylisted by logout; checked by the bearer middleware.
+pub fn access_revocations() -> &'static RevocationStore {
+    ACCESS_REVOCATIONS.get_or_init(RevocationStore::new)
+}
+
+/// MFA challenge ids that were exchanged for tokens or ran out of attempts.
+pub fn mfa_challenge_revocations() -> &'static RevocationStore {
+    MFA_CHALLENGE_REVOCATIONS.get_or_init(RevocationStore::new)
+}
+
+/// Emailed verification, reset and magic-link token ids that were already redeemed.
+pub fn email_token_revocations() -> &'static RevocationStore {
+    EMAIL_TOKEN_REVOCATIONS.get_or_init(RevocationStore::new)
+}
+
+/// Exchanged access tokens issued without a session, such as impersonation tokens.
+pub fn sessionless_access_tokens() -> &'static UserTokenIndex {
+    SESSIONLESS_ACCESS_TOKENS.get_or_init(UserTokenIndex::new)
+}
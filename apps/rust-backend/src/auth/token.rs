@@ -1,103 +1,163 @@
-use crate::auth::claims::AuthenticatedUser;
+use crate::auth::claims::{timestamp_claim, Actor, AuthenticatedUser};
 use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::permissions;
+use crate::auth::revocation::{access_revocations, refresh_family_revocations, sessionless_access_tokens};
+use crate::auth::validation::ValidationRules;
 use crate::config::get_config;
 
-use pasetors::claims::{Claims, ClaimsValidationRules};
-use pasetors::keys::{AsymmetricPublicKey, AsymmetricSecretKey};
-use pasetors::public::{self, Public, version4::V4};
-use pasetors::token::{TrustedToken, UntrustedToken};
+use pasetors::claims::Claims;
+use pasetors::footer::Footer;
+use pasetors::paserk::Id;
+use pasetors::public;
+use pasetors::token::{Public, UntrustedToken};
+use pasetors::version4::{PublicToken, V4};
 use serde_json::json;
 use std::convert::TryFrom;
-use time::OffsetDateTime;
 
-pub fn issue_access_token(sub: &str, roles: &[String]) -> AuthResult<String> {
-    let cfg = get_config();
+/// `session_id` is the refresh token family the token was issued to, carried as
+/// `sid` so that ending the session also cuts off its outstanding access tokens.
+pub fn issue_access_token(
+    sub: &str,
+    roles: &[String],
+    scope: &[String],
+    session_id: Option<&str>,
+) -> AuthResult<String> {
+    let mut claims = base_claims(scope, get_config().access_ttl_min)?;
+    claims
+        .subject(sub)
+        .map_err(|e| AuthError::Internal(format!("add sub: {e}")))?;
+    claims
+        .add_additional("roles", json!(roles))
+        .map_err(|e| AuthError::Internal(format!("add roles: {e}")))?;
+    if let Some(sid) = session_id {
+        claims
+            .add_additional("sid", json!(sid))
+            .map_err(|e| AuthError::Internal(format!("add sid: {e}")))?;
+    }
+    sign_claims(&claims)
+}
 
-    // Build registered and custom claims
-    let mut claims = Claims::new().map_err(|e| AuthError::Internal(format!("claims new: {e}")))?;
-    let now = OffsetDateTime::now_utc();
-    let exp = now + time::Duration::minutes(cfg.access_ttl_min);
+/// Token for a machine principal (client credentials grant): no `sub`, no roles,
+/// only the client and the scopes it was granted.
+pub fn issue_client_token(client_id: &str, scope: &[String]) -> AuthResult<String> {
+    let mut claims = base_claims(scope, get_config().access_ttl_min)?;
     claims
-        .add_iat(now.unix_timestamp())
-        .map_err(|e| AuthError::Internal(format!("add_iat: {e}")))?;
+        .add_additional("client_id", json!(client_id))
+        .map_err(|e| AuthError::Internal(format!("add client_id: {e}")))?;
+    sign_claims(&claims)
+}
+
+/// Token for `sub` obtained through token exchange, naming the real `actor` in
+/// `act`. It lives `TOKEN_EXCHANGE_TTL_MIN` minutes. `session_id` is the subject
+/// token's `sid`, so the exchanged token ends with that session. Without one, as
+/// for impersonation, its id is recorded against `sub` instead, so that
+/// `revoke_user_access_tokens` still reaches it.
+pub fn issue_exchanged_token(
+    sub: &str,
+    roles: &[String],
+    scope: &[String],
+    session_id: Option<&str>,
+    actor: &Actor,
+) -> AuthResult<String> {
+    let mut claims = base_claims(scope, get_config().token_exchange_ttl_min)?;
     claims
-        .add_nbf(now.unix_timestamp())
-        .map_err(|e| AuthError::Internal(format!("add_nbf: {e}")))?;
+        .subject(sub)
+        .map_err(|e| AuthError::Internal(format!("add sub: {e}")))?;
     claims
-        .add_exp(exp.unix_timestamp())
-        .map_err(|e| AuthError::Internal(format!("add_exp: {e}")))?;
+        .add_additional("roles", json!(roles))
+        .map_err(|e| AuthError::Internal(format!("add roles: {e}")))?;
+    claims
+        .add_additional("act", json!(actor))
+        .map_err(|e| AuthError::Internal(format!("add act: {e}")))?;
+    match session_id {
+        Some(sid) => claims
+            .add_additional("sid", json!(sid))
+            .map_err(|e| AuthError::Internal(format!("add sid: {e}")))?,
+        None => {
+            let jti = claims
+                .get_claim("jti")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| AuthError::Internal("missing jti".into()))?;
+            sessionless_access_tokens().record(sub, jti, timestamp_claim(&claims, "exp")?);
+        }
+    }
+    sign_claims(&claims)
+}
+
+/// Registered claims shared by every access token; iat/nbf/exp are set by pasetors.
+fn base_claims(scope: &[String], ttl_min: i64) -> AuthResult<Claims> {
+    let cfg = get_config();
+
+    let ttl = time::Duration::minutes(ttl_min)
+        .try_into()
+        .map_err(|e| AuthError::Internal(format!("access ttl: {e}")))?;
+    let mut claims = Claims::new_expires_in(&ttl)
+        .map_err(|e| AuthError::Internal(format!("claims new: {e}")))?;
 
     claims
-        .add_additional("iss", json!(cfg.iss))
+        .issuer(&cfg.iss)
         .map_err(|e| AuthError::Internal(format!("add iss: {e}")))?;
     claims
-        .add_additional("aud", json!(cfg.aud))
+        .audience(&cfg.aud)
         .map_err(|e| AuthError::Internal(format!("add aud: {e}")))?;
     claims
-        .add_additional("sub", json!(sub))
-        .map_err(|e| AuthError::Internal(format!("add sub: {e}")))?;
-    claims
-        .add_additional("roles", json!(roles))
-        .map_err(|e| AuthError::Internal(format!("add roles: {e}")))?;
+        .add_additional("scope", json!(permissions::to_scope(scope)))
+        .map_err(|e| AuthError::Internal(format!("add scope: {e}")))?;
     claims
-        .add_additional("jti", json!(uuid::Uuid::new_v4().to_string()))
+        .token_identifier(&uuid::Uuid::new_v4().to_string())
         .map_err(|e| AuthError::Internal(format!("add jti: {e}")))?;
+    Ok(claims)
+}
 
-    // Convert our Ed25519 secret key bytes into pasetors key
-    let sk_bytes = cfg.signing_key.to_bytes();
-    let sk = AsymmetricSecretKey::<V4>::try_from(sk_bytes.as_slice())
-        .map_err(|e| AuthError::CryptoError(format!("secret key error: {e}")))?;
+fn sign_claims(claims: &Claims) -> AuthResult<String> {
+    let cfg = get_config();
 
-    // No footer/implicit assertion for now
-    let token = public::sign(&sk, &claims, None, None)
-        .map_err(|e| AuthError::CryptoError(format!("sign error: {e}")))?;
+    // Sign with the active key and name it in the footer so verifiers can pick it
+    let key = cfg.keyring.signing_key();
+    let sk = key
+        .secret
+        .as_ref()
+        .ok_or_else(|| AuthError::CryptoError("active key has no secret".into()))?;
+    let mut footer = Footer::new();
+    footer.key_id(&Id::from(&key.public));
 
-    Ok(token)
+    public::sign(sk, claims, Some(&footer), None)
+        .map_err(|e| AuthError::CryptoError(format!("sign error: {e}")))
 }
 
 pub fn verify_access_token(token: &str) -> AuthResult<AuthenticatedUser> {
     let cfg = get_config();
 
-    // Convert verifying key
-    let pk_bytes = cfg.verifying_key.to_bytes();
-    let pk = AsymmetricPublicKey::<V4>::try_from(pk_bytes.as_slice())
-        .map_err(|e| AuthError::CryptoError(format!("public key error: {e}")))?;
-
-    // Parse and verify
+    // Parse, then select the verifying key named by the footer's kid
     let untrusted = UntrustedToken::<Public, V4>::try_from(token)
         .map_err(|_| AuthError::InvalidTokenFormat)?;
 
-    let rules = ClaimsValidationRules::new();
+    let mut footer = Footer::new();
+    footer
+        .parse_bytes(untrusted.untrusted_footer())
+        .map_err(|_| AuthError::InvalidTokenFormat)?;
+    let kid = footer
+        .get_claim("kid")
+        .and_then(|v| v.as_str())
+        .ok_or(AuthError::InvalidTokenFormat)?;
+    let key = cfg.keyring.verifying_key(kid).ok_or(AuthError::UnknownKeyId)?;
 
-    let trusted: TrustedToken = public::verify(&pk, &untrusted, &rules, None, None)
+    // Signature first; claims are validated against the configured rules below
+    let trusted = PublicToken::verify(&key.public, &untrusted, Some(untrusted.untrusted_footer()), None)
         .map_err(|_| AuthError::SignatureVerificationFailed)?;
+    let payload = &Claims::from_string(trusted.payload())
+        .map_err(|_| AuthError::ClaimValidationFailed("malformed payload".into()))?;
 
-    // Extract custom claims from payload
-    let payload = trusted
-        .payload_claims()
-        .ok_or_else(|| AuthError::ClaimValidationFailed("missing payload".into()))?;
-
-    // Validate iss/aud/sub/roles
-    let iss = payload
-        .get_claim("iss")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .ok_or_else(|| AuthError::MissingClaim("iss".into()))?;
-    if iss != cfg.iss {
-        return Err(AuthError::ClaimValidationFailed("issuer mismatch".into()));
-    }
+    ValidationRules::from_config(cfg).validate(payload, time::OffsetDateTime::now_utc().unix_timestamp())?;
 
-    let aud = payload
-        .get_claim("aud")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .ok_or_else(|| AuthError::MissingClaim("aud".into()))?;
-    if aud != cfg.aud {
-        return Err(AuthError::ClaimValidationFailed("audience mismatch".into()));
-    }
+    let string_claim = |name: &str| payload.get_claim(name).and_then(|v| v.as_str().map(|s| s.to_string()));
 
-    let sub = payload
-        .get_claim("sub")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .ok_or_else(|| AuthError::MissingClaim("sub".into()))?;
+    // A token names a user, a client, or (for a client acting for a user) both
+    let sub = string_claim("sub");
+    let client_id = string_claim("client_id");
+    if sub.is_none() && client_id.is_none() {
+        return Err(AuthError::MissingClaim("sub".into()));
+    }
 
     let roles: Vec<String> = payload
         .get_claim("roles")
@@ -107,15 +167,97 @@ pub fn verify_access_token(token: &str) -> AuthResult<AuthenticatedUser> {
         .filter_map(|v| v.as_str().map(|s| s.to_string()))
         .collect();
 
-    let jti = payload
-        .get_claim("jti")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
+    let scope = payload
+        .get_claim("scope")
+        .and_then(|v| v.as_str())
+        .map(permissions::from_scope)
         .unwrap_or_default();
 
-    let exp = payload
-        .get_claim("exp")
-        .and_then(|v| v.as_i64())
-        .ok_or_else(|| AuthError::MissingClaim("exp".into()))?;
+    let jti = string_claim("jti").unwrap_or_default();
+    let session_id = string_claim("sid");
+    let actor = match payload.get_claim("act") {
+        Some(act) => Some(
+            serde_json::from_value::<Actor>(act.clone())
+                .map_err(|_| AuthError::ClaimValidationFailed("malformed act".into()))?,
+        ),
+        None => None,
+    };
+
+    let exp = timestamp_claim(payload, "exp")?;
+
+    Ok(AuthenticatedUser {
+        user_id: sub.unwrap_or_default(),
+        client_id,
+        roles,
+        scope,
+        jti,
+        exp,
+        session_id,
+        actor,
+        api_key_id: None,
+    })
+}
+
+/// Denylists an access token until it would have expired anyway.
+pub fn revoke_access_token(user: &AuthenticatedUser) {
+    if !user.jti.is_empty() {
+        access_revocations().revoke(&user.jti, user.exp);
+    }
+}
+
+/// Denylists the sessionless access tokens issued for `user_id`, which ending
+/// its sessions alone would not reach.
+pub fn revoke_user_access_tokens(user_id: &str) {
+    for (jti, exp) in sessionless_access_tokens().take(user_id) {
+        access_revocations().revoke(&jti, exp);
+    }
+}
+
+/// True when the token itself was revoked or the session it belongs to has ended.
+pub fn is_access_token_revoked(user: &AuthenticatedUser) -> bool {
+    access_revocations().is_revoked(&user.jti)
+        || user
+            .session_id
+            .as_deref()
+            .is_some_and(|sid| refresh_family_revocations().is_revoked(sid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::refresh::revoke_refresh_family;
+
+    fn support_agent() -> Actor {
+        Actor {
+            sub: Some("agent".into()),
+            client_id: None,
+            act: None,
+        }
+    }
+
+    #[test]
+    fn delegated_token_ends_with_the_subject_session() {
+        let sid = uuid::Uuid::new_v4().to_string();
+        let token = issue_exchanged_token("delegator", &[], &[], Some(&sid), &support_agent()).unwrap();
+        let user = verify_access_token(&token).unwrap();
+        assert_eq!(user.session_id.as_deref(), Some(sid.as_str()));
+        assert!(!is_access_token_revoked(&user));
+
+        revoke_refresh_family(&sid);
+        assert!(is_access_token_revoked(&user));
+    }
+
+    #[test]
+    fn sessionless_token_is_revoked_with_its_user() {
+        let token = issue_exchanged_token("impersonated", &[], &[], None, &support_agent()).unwrap();
+        let other = issue_exchanged_token("bystander", &[], &[], None, &support_agent()).unwrap();
+        let user = verify_access_token(&token).unwrap();
+        let other = verify_access_token(&other).unwrap();
+        assert!(user.session_id.is_none());
+        assert!(!is_access_token_revoked(&user));
 
-    Ok(AuthenticatedUser { user_id: sub, roles, jti, exp })
+        revoke_user_access_tokens("impersonated");
+        assert!(is_access_token_revoked(&user));
+        assert!(!is_access_token_revoked(&other));
+    }
 }
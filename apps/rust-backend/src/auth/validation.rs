@@ -0,0 +1,187 @@
+use pasetors::claims::Claims;
+
+use crate::auth::claims::timestamp_claim;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::AppConfig;
+
+/// Claim checks applied to access tokens once their signature has been verified.
+///
+/// pasetors' own `ClaimsValidationRules` compares times exactly and accepts a single
+/// issuer and audience, which is too strict for several frontends and containers
+/// whose clocks drift; these rules are built from configuration instead.
+#[derive(Debug, Clone)]
+pub struct ValidationRules {
+    /// Allowed clock skew, applied to `exp`, `nbf`, `iat` and the maximum age
+    pub leeway_secs: i64,
+    pub issuers: Vec<String>,
+    pub audiences: Vec<String>,
+    /// Claims that must be present in addition to `exp`
+    pub required_claims: Vec<String>,
+    /// Tokens issued longer ago than this are rejected regardless of `exp`
+    pub max_age_secs: Option<i64>,
+}
+
+impl ValidationRules {
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        Self {
+            leeway_secs: cfg.token_leeway_secs,
+            issuers: cfg.accepted_issuers.clone(),
+            audiences: cfg.accepted_audiences.clone(),
+            required_claims: cfg.required_claims.clone(),
+            max_age_secs: cfg.max_token_age_secs,
+        }
+    }
+
+    /// Validates `claims` as of `now` (unix seconds). Taking the time as an argument
+    /// keeps the checks deterministic for callers with a fixed clock.
+    pub fn validate(&self, claims: &Claims, now: i64) -> AuthResult<()> {
+        for name in &self.required_claims {
+            // Machine tokens have no user subject; their client_id stands in for sub
+            let present = claims.contains_claim(name) || (name == "sub" && claims.contains_claim("client_id"));
+            if !present {
+                return Err(AuthError::MissingClaim(name.clone()));
+            }
+        }
+
+        let exp = timestamp_claim(claims, "exp")?;
+        if now > exp + self.leeway_secs {
+            return Err(AuthError::TokenExpired);
+        }
+
+        if claims.contains_claim("nbf") && timestamp_claim(claims, "nbf")? > now + self.leeway_secs {
+            return Err(AuthError::TokenNotYetValid);
+        }
+
+        let iat = claims
+            .contains_claim("iat")
+            .then(|| timestamp_claim(claims, "iat"))
+            .transpose()?;
+        if let Some(iat) = iat
+            && iat > now + self.leeway_secs
+        {
+            return Err(AuthError::TokenNotYetValid);
+        }
+        if let Some(max_age) = self.max_age_secs {
+            // Without iat the age is unknown, so a configured maximum makes it mandatory
+            let iat = iat.ok_or_else(|| AuthError::MissingClaim("iat".into()))?;
+            if now - iat > max_age + self.leeway_secs {
+                return Err(AuthError::TokenExpired);
+            }
+        }
+
+        let iss = string_claim(claims, "iss")?;
+        if !self.issuers.iter().any(|accepted| accepted == iss) {
+            return Err(AuthError::ClaimValidationFailed("issuer mismatch".into()));
+        }
+
+        let aud = string_claim(claims, "aud")?;
+        if !self.audiences.iter().any(|accepted| accepted == aud) {
+            return Err(AuthError::InvalidAudience);
+        }
+
+        Ok(())
+    }
+}
+
+fn string_claim<'a>(claims: &'a Claims, name: &str) -> AuthResult<&'a str> {
+    claims
+        .get_claim(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| AuthError::MissingClaim(name.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use time::format_description::well_known::Rfc3339;
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn rules() -> ValidationRules {
+        ValidationRules {
+            leeway_secs: 30,
+            issuers: vec!["auth".into(), "legacy-auth".into()],
+            audiences: vec!["web".into(), "mobile".into()],
+            required_claims: vec!["sub".into(), "jti".into()],
+            max_age_secs: None,
+        }
+    }
+
+    fn at(offset: i64) -> Value {
+        json!(OffsetDateTime::from_unix_timestamp(NOW + offset).unwrap().format(&Rfc3339).unwrap())
+    }
+
+    /// A valid token issued just now; `changes` overrides or, with `null`, drops claims.
+    fn claims(changes: Value) -> Claims {
+        let mut body = json!({
+            "iss": "auth", "aud": "web", "sub": "user-1", "jti": "token-1",
+            "iat": at(0), "nbf": at(0), "exp": at(900),
+        });
+        for (name, value) in changes.as_object().unwrap() {
+            match value {
+                Value::Null => body.as_object_mut().unwrap().remove(name),
+                _ => body.as_object_mut().unwrap().insert(name.clone(), value.clone()),
+            };
+        }
+        Claims::from_string(&body.to_string()).unwrap()
+    }
+
+    #[test]
+    fn leeway_applies_to_exp_nbf_and_iat() {
+        let rules = rules();
+        assert!(rules.validate(&claims(json!({ "exp": at(-30) })), NOW).is_ok());
+        assert!(matches!(rules.validate(&claims(json!({ "exp": at(-31) })), NOW), Err(AuthError::TokenExpired)));
+
+        assert!(rules.validate(&claims(json!({ "nbf": at(30) })), NOW).is_ok());
+        let early = rules.validate(&claims(json!({ "nbf": at(31) })), NOW);
+        assert!(matches!(early, Err(AuthError::TokenNotYetValid)));
+
+        assert!(rules.validate(&claims(json!({ "iat": at(30) })), NOW).is_ok());
+        let future = rules.validate(&claims(json!({ "iat": at(31) })), NOW);
+        assert!(matches!(future, Err(AuthError::TokenNotYetValid)));
+
+        // Both are optional without a maximum age
+        assert!(rules.validate(&claims(json!({ "iat": null, "nbf": null })), NOW).is_ok());
+    }
+
+    #[test]
+    fn any_accepted_issuer_and_audience_passes() {
+        let rules = rules();
+        assert!(rules.validate(&claims(json!({ "iss": "legacy-auth", "aud": "mobile" })), NOW).is_ok());
+
+        let issuer = rules.validate(&claims(json!({ "iss": "elsewhere" })), NOW);
+        assert!(matches!(issuer, Err(AuthError::ClaimValidationFailed(_))));
+        let audience = rules.validate(&claims(json!({ "aud": "admin" })), NOW);
+        assert!(matches!(audience, Err(AuthError::InvalidAudience)));
+        let missing = rules.validate(&claims(json!({ "aud": null })), NOW);
+        assert!(matches!(missing, Err(AuthError::MissingClaim(name)) if name == "aud"));
+    }
+
+    #[test]
+    fn required_claims_must_be_present() {
+        let rules = rules();
+        let missing = rules.validate(&claims(json!({ "jti": null })), NOW);
+        assert!(matches!(missing, Err(AuthError::MissingClaim(name)) if name == "jti"));
+        let missing = rules.validate(&claims(json!({ "sub": null })), NOW);
+        assert!(matches!(missing, Err(AuthError::MissingClaim(name)) if name == "sub"));
+
+        // Machine tokens carry client_id in place of sub
+        let machine = claims(json!({ "sub": null, "client_id": "billing-service" }));
+        assert!(rules.validate(&machine, NOW).is_ok());
+    }
+
+    #[test]
+    fn max_age_limits_tokens_regardless_of_exp() {
+        let rules = ValidationRules { max_age_secs: Some(600), ..rules() };
+        assert!(rules.validate(&claims(json!({ "iat": at(-630) })), NOW).is_ok());
+        let old = rules.validate(&claims(json!({ "iat": at(-631) })), NOW);
+        assert!(matches!(old, Err(AuthError::TokenExpired)));
+
+        // With a maximum age, iat is mandatory
+        let unknown = rules.validate(&claims(json!({ "iat": null })), NOW);
+        assert!(matches!(unknown, Err(AuthError::MissingClaim(name)) if name == "iat"));
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::clients::{Client, ClientRepository};
+
+/// Process-local repository; state is lost on restart. Meant for tests and quick demos.
+#[derive(Debug, Default)]
+pub struct InMemoryClientRepository {
+    clients: RwLock<HashMap<String, Client>>,
+}
+
+impl InMemoryClientRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ClientRepository for InMemoryClientRepository {
+    async fn find(&self, id: &str) -> AuthResult<Option<Client>> {
+        let clients = self.clients.read().expect("client repository lock");
+        Ok(clients.get(id).cloned())
+    }
+
+    async fn list(&self) -> AuthResult<Vec<Client>> {
+        let clients = self.clients.read().expect("client repository lock");
+        let mut list: Vec<Client> = clients.values().cloned().collect();
+        list.sort_by_key(|c| c.created_at);
+        Ok(list)
+    }
+
+    async fn create(&self, client: Client) -> AuthResult<Client> {
+        let mut clients = self.clients.write().expect("client repository lock");
+        if clients.contains_key(&client.id) {
+            return Err(AuthError::ClientAlreadyExists);
+        }
+        clients.insert(client.id.clone(), client.clone());
+        Ok(client)
+    }
+
+    async fn delete(&self, id: &str) -> AuthResult<bool> {
+        let mut clients = self.clients.write().expect("client repository lock");
+        Ok(clients.remove(id).is_some())
+    }
+
+    async fn set_secret_hash(&self, id: &str, secret_hash: &str) -> AuthResult<()> {
+        let mut clients = self.clients.write().expect("client repository lock");
+        let client = clients.get_mut(id).ok_or(AuthError::InvalidClient)?;
+        client.secret_hash = secret_hash.to_string();
+        client.updated_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn duplicate_client_id_is_a_client_conflict() {
+        let repo = InMemoryClientRepository::new();
+        let client = Client::new("worker".into(), "Worker".into(), "hash".into(), Vec::new());
+        repo.create(client.clone()).await.unwrap();
+
+        let err = repo.create(client).await.unwrap_err();
+        assert!(matches!(err, AuthError::ClientAlreadyExists));
+        assert_eq!(err.code(), "client_already_exists");
+        assert_eq!(err.status_code(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+use std::sync::Arc;
+
+use actix_web::web;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::password::verify_password;
+use crate::config::get_config;
+use crate::storage::StorageBackend;
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemoryClientRepository;
+pub use sqlite::SqliteClientRepository;
+
+/// A confidential OAuth client, such as a background worker, that obtains tokens
+/// for itself with the client credentials grant.
+#[derive(Clone)]
+pub struct Client {
+    /// The public `client_id`
+    pub id: String,
+    pub name: String,
+    /// SHA-256 hex digest of the client secret, or an Argon2 hash from before
+    /// secrets were hashed that way, replaced at the next successful use
+    pub secret_hash: String,
+    /// Permissions the client may request; tokens carry these or a subset
+    pub scopes: Vec<String>,
+    pub disabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Client {
+    pub fn new(id: String, name: String, secret_hash: String, scopes: Vec<String>) -> Self {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        Self {
+            id,
+            name,
+            secret_hash,
+            scopes,
+            disabled: false,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("scopes", &self.scopes)
+            .field("disabled", &self.disabled)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .finish()
+    }
+}
+
+#[async_trait]
+pub trait ClientRepository: Send + Sync {
+    async fn find(&self, id: &str) -> AuthResult<Option<Client>>;
+
+    async fn list(&self) -> AuthResult<Vec<Client>>;
+
+    /// Fails with `ClientAlreadyExists` when the client id is taken.
+    async fn create(&self, client: Client) -> AuthResult<Client>;
+
+    /// Returns `false` when there was no such client.
+    async fn delete(&self, id: &str) -> AuthResult<bool>;
+
+    async fn set_secret_hash(&self, id: &str, secret_hash: &str) -> AuthResult<()>;
+}
+
+/// Builds the repository for the configured storage backend.
+pub fn repository(storage: &StorageBackend) -> web::Data<dyn ClientRepository> {
+    let repo: Arc<dyn ClientRepository> = match storage {
+        StorageBackend::Memory => Arc::new(InMemoryClientRepository::new()),
+        StorageBackend::Sqlite(pool) => Arc::new(SqliteClientRepository::new(pool.clone())),
+    };
+    web::Data::from(repo)
+}
+
+/// 256 bits per secret. That is enough that a plain SHA-256 digest is safe to
+/// store, and checking it costs an unauthenticated caller's request next to nothing,
+/// unlike Argon2.
+const SECRET_BYTES: usize = 32;
+
+/// Random secret for a newly registered client, shown once and stored hashed.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Checks a client id + secret pair. Unknown, disabled and wrong-secret cases all
+/// collapse into `InvalidClient`.
+pub async fn authenticate_client(repo: &dyn ClientRepository, id: &str, secret: &str) -> AuthResult<Client> {
+    let Some(mut client) = repo.find(id).await? else {
+        return Err(AuthError::InvalidClient);
+    };
+
+    // Secrets registered before the switch to SHA-256 are still Argon2 hashes; each
+    // is checked the slow way once, then replaced
+    let valid = if client.secret_hash.starts_with("$argon2") {
+        let (presented, hash) = (secret.to_string(), client.secret_hash.clone());
+        let valid = web::block(move || verify_password(&presented, &hash))
+            .await
+            .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))??;
+        if valid && !client.disabled {
+            client.secret_hash = hash_secret(secret);
+            repo.set_secret_hash(&client.id, &client.secret_hash).await?;
+        }
+        valid
+    } else {
+        digests_match(&hash_secret(secret), &client.secret_hash)
+    };
+
+    if !valid || client.disabled {
+        return Err(AuthError::InvalidClient);
+    }
+    Ok(client)
+}
+
+/// Compares in time independent of where the digests differ.
+fn digests_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Registers the bootstrap client from `SEED_CLIENT_*` so services can get tokens
+/// on a fresh install.
+pub async fn seed_from_config(repo: &dyn ClientRepository) -> AuthResult<()> {
+    let cfg = get_config();
+    let (Some(id), Some(secret)) = (cfg.seed_client_id.as_deref(), cfg.seed_client_secret.as_deref()) else {
+        return Ok(());
+    };
+    if repo.find(id).await?.is_some() {
+        return Ok(());
+    }
+
+    let client = Client::new(
+        id.to_string(),
+        id.to_string(),
+        hash_secret(secret),
+        cfg.seed_client_scopes.clone(),
+    );
+    repo.create(client).await?;
+    println!("[auth] seeded client {id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::password::hash_password;
+
+    #[actix_web::test]
+    async fn secrets_are_checked_against_their_digest() {
+        let repo = InMemoryClientRepository::new();
+        let secret = generate_secret();
+        repo.create(Client::new("worker".into(), "Worker".into(), hash_secret(&secret), Vec::new()))
+            .await
+            .unwrap();
+
+        assert!(authenticate_client(&repo, "worker", &secret).await.is_ok());
+        let wrong = authenticate_client(&repo, "worker", &generate_secret()).await;
+        assert!(matches!(wrong, Err(AuthError::InvalidClient)));
+        let unknown = authenticate_client(&repo, "nobody", &secret).await;
+        assert!(matches!(unknown, Err(AuthError::InvalidClient)));
+    }
+
+    #[actix_web::test]
+    async fn argon2_hashes_are_replaced_on_use() {
+        let repo = InMemoryClientRepository::new();
+        let secret = generate_secret();
+        let legacy = hash_password(&secret).unwrap();
+        repo.create(Client::new("worker".into(), "Worker".into(), legacy, Vec::new()))
+            .await
+            .unwrap();
+
+        assert!(authenticate_client(&repo, "worker", &secret).await.is_ok());
+        let stored = repo.find("worker").await.unwrap().unwrap();
+        assert_eq!(stored.secret_hash, hash_secret(&secret));
+        assert!(authenticate_client(&repo, "worker", &secret).await.is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::clients::{Client, ClientRepository};
+use crate::storage::storage_error;
+
+const CLIENT_COLUMNS: &str = "id, name, secret_hash, scopes, disabled, created_at, updated_at";
+
+/// SQLite-backed repository over the pool opened by `storage::connect_sqlite`.
+#[derive(Debug, Clone)]
+pub struct SqliteClientRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteClientRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ClientRepository for SqliteClientRepository {
+    async fn find(&self, id: &str) -> AuthResult<Option<Client>> {
+        let sql = format!("SELECT {CLIENT_COLUMNS} FROM oauth_clients WHERE id = ?1");
+        let row = sqlx::query(&sql)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        row.as_ref().map(row_to_client).transpose()
+    }
+
+    async fn list(&self) -> AuthResult<Vec<Client>> {
+        let sql = format!("SELECT {CLIENT_COLUMNS} FROM oauth_clients ORDER BY created_at");
+        let rows = sqlx::query(&sql)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        rows.iter().map(row_to_client).collect()
+    }
+
+    async fn create(&self, client: Client) -> AuthResult<Client> {
+        let scopes = serde_json::to_string(&client.scopes).map_err(storage_error)?;
+        sqlx::query(
+            "INSERT INTO oauth_clients (id, name, secret_hash, scopes, disabled, created_at, updated_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )
+        .bind(&client.id)
+        .bind(&client.name)
+        .bind(&client.secret_hash)
+        .bind(scopes)
+        .bind(client.disabled)
+        .bind(client.created_at)
+        .bind(client.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => AuthError::ClientAlreadyExists,
+            _ => storage_error(e),
+        })?;
+        Ok(client)
+    }
+
+    async fn delete(&self, id: &str) -> AuthResult<bool> {
+        let result = sqlx::query("DELETE FROM oauth_clients WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_secret_hash(&self, id: &str, secret_hash: &str) -> AuthResult<()> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let result = sqlx::query("UPDATE oauth_clients SET secret_hash = ?2, updated_at = ?3 WHERE id = ?1")
+            .bind(id)
+            .bind(secret_hash)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::InvalidClient);
+        }
+        Ok(())
+    }
+}
+
+fn row_to_client(row: &SqliteRow) -> AuthResult<Client> {
+    let scopes: String = row.try_get("scopes").map_err(storage_error)?;
+    Ok(Client {
+        id: row.try_get("id").map_err(storage_error)?,
+        name: row.try_get("name").map_err(storage_error)?,
+        secret_hash: row.try_get("secret_hash").map_err(storage_error)?,
+        scopes: serde_json::from_str(&scopes).map_err(storage_error)?,
+        disabled: row.try_get("disabled").map_err(storage_error)?,
+        created_at: row.try_get("created_at").map_err(storage_error)?,
+        updated_at: row.try_get("updated_at").map_err(storage_error)?,
+    })
+}
@@ -1,43 +1,151 @@
 use std::sync::OnceLock;
 use std::{env, fmt};
 
-use base64::engine::general_purpose::STANDARD as BASE64;
-use base64::Engine;
 use dotenvy::dotenv;
 use pasetors::keys::{AsymmetricKeyPair, AsymmetricPublicKey, AsymmetricSecretKey, Generate, SymmetricKey};
 use pasetors::version4::V4;
+use serde::Deserialize;
+
+use crate::auth::key_material::{parse_public_key, parse_secret_key, parse_symmetric_key};
+use crate::auth::keys::{KeyEntry, KeyStatus, Keyring};
+use crate::oidc::OidcProviderConfig;
+use crate::rate_limit::BucketLimit;
 
 pub struct AppConfig {
-    pub signing_key: AsymmetricSecretKey<V4>,   // v4.public secret key
-    pub verifying_key: AsymmetricPublicKey<V4>, // v4.public public key
-    pub refresh_key: SymmetricKey<V4>,          // v4.local symmetric key
+    pub keyring: Keyring,              // v4.public signing/verifying keys
+    pub refresh_key: SymmetricKey<V4>, // v4.local symmetric key
+    pub mfa_key: SymmetricKey<V4>,     // v4.local key sealing TOTP secrets and MFA challenges
     pub iss: String,
     pub aud: String,
+    pub accepted_issuers: Vec<String>,
+    pub accepted_audiences: Vec<String>,
+    pub required_claims: Vec<String>,
+    pub token_leeway_secs: i64,
+    pub max_token_age_secs: Option<i64>,
     pub access_ttl_min: i64,
+    pub keys_cache_max_age_secs: u32,
     pub refresh_ttl_days: i64,
+    pub token_exchange_ttl_min: i64,
+    pub impersonation_permission: String,
+    pub totp_issuer: String,
+    pub totp_skew_steps: i64,
+    pub mfa_challenge_ttl_secs: i64,
+    pub recovery_code_count: usize,
+    pub api_key_default_ttl_days: i64,
+    pub api_key_max_ttl_days: i64,
+    pub signup_enabled: bool,
+    pub signup_default_roles: Vec<String>,
+    pub require_verified_email: bool,
+    pub email_verification_ttl_secs: i64,
+    pub password_reset_ttl_secs: i64,
+    pub magic_link_ttl_secs: i64,
+    pub mail_link_base_url: String,
+    pub mail_transport: String,
+    pub mail_from: String,
+    pub mail_file_dir: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_timeout_secs: u64,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub oidc_redirect_base_url: String,
+    pub oidc_post_login_redirect: Option<String>,
+    pub oidc_auto_create_users: bool,
+    pub oidc_link_verified_email: bool,
+    pub oidc_default_roles: Vec<String>,
+    pub oidc_http_timeout_secs: u64,
+    pub oidc_http_client: String,
+    pub oidc_curl_path: String,
     pub cookie_secure: bool,
     pub cookie_domain: String,
     pub cookie_path: String,
     pub refresh_cookie_name: String,
     pub cors_allowed_origin: String,
+    pub rate_limit_enabled: bool,
+    pub rate_limit_trust_forwarded: bool,
+    pub rate_limit_login_ip: BucketLimit,
+    pub rate_limit_login_account: BucketLimit,
+    pub rate_limit_refresh_ip: BucketLimit,
+    pub rate_limit_refresh_account: BucketLimit,
     pub server_port: u16,
     pub dev_fallback_keys: bool,
+    pub user_repository: String,
+    pub database_url: String,
+    pub seed_user_email: String,
+    pub seed_user_password: Option<String>,
+    pub seed_user_roles: Vec<String>,
+    pub seed_user_permissions: Vec<String>,
+    pub seed_client_id: Option<String>,
+    pub seed_client_secret: Option<String>,
+    pub seed_client_scopes: Vec<String>,
 }
 
 impl fmt::Debug for AppConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AppConfig")
+            .field("keyring", &self.keyring)
             .field("iss", &self.iss)
             .field("aud", &self.aud)
+            .field("accepted_issuers", &self.accepted_issuers)
+            .field("accepted_audiences", &self.accepted_audiences)
+            .field("required_claims", &self.required_claims)
+            .field("token_leeway_secs", &self.token_leeway_secs)
+            .field("max_token_age_secs", &self.max_token_age_secs)
             .field("access_ttl_min", &self.access_ttl_min)
+            .field("keys_cache_max_age_secs", &self.keys_cache_max_age_secs)
             .field("refresh_ttl_days", &self.refresh_ttl_days)
+            .field("token_exchange_ttl_min", &self.token_exchange_ttl_min)
+            .field("impersonation_permission", &self.impersonation_permission)
+            .field("totp_issuer", &self.totp_issuer)
+            .field("totp_skew_steps", &self.totp_skew_steps)
+            .field("mfa_challenge_ttl_secs", &self.mfa_challenge_ttl_secs)
+            .field("recovery_code_count", &self.recovery_code_count)
+            .field("api_key_default_ttl_days", &self.api_key_default_ttl_days)
+            .field("api_key_max_ttl_days", &self.api_key_max_ttl_days)
+            .field("signup_enabled", &self.signup_enabled)
+            .field("signup_default_roles", &self.signup_default_roles)
+            .field("require_verified_email", &self.require_verified_email)
+            .field("email_verification_ttl_secs", &self.email_verification_ttl_secs)
+            .field("password_reset_ttl_secs", &self.password_reset_ttl_secs)
+            .field("magic_link_ttl_secs", &self.magic_link_ttl_secs)
+            .field("mail_link_base_url", &self.mail_link_base_url)
+            .field("mail_transport", &self.mail_transport)
+            .field("mail_from", &self.mail_from)
+            .field("mail_file_dir", &self.mail_file_dir)
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("smtp_username", &self.smtp_username)
+            .field("smtp_timeout_secs", &self.smtp_timeout_secs)
+            .field("oidc_providers", &self.oidc_providers)
+            .field("oidc_redirect_base_url", &self.oidc_redirect_base_url)
+            .field("oidc_post_login_redirect", &self.oidc_post_login_redirect)
+            .field("oidc_auto_create_users", &self.oidc_auto_create_users)
+            .field("oidc_link_verified_email", &self.oidc_link_verified_email)
+            .field("oidc_default_roles", &self.oidc_default_roles)
+            .field("oidc_http_timeout_secs", &self.oidc_http_timeout_secs)
+            .field("oidc_http_client", &self.oidc_http_client)
+            .field("oidc_curl_path", &self.oidc_curl_path)
             .field("cookie_secure", &self.cookie_secure)
             .field("cookie_domain", &self.cookie_domain)
             .field("cookie_path", &self.cookie_path)
             .field("refresh_cookie_name", &self.refresh_cookie_name)
             .field("cors_allowed_origin", &self.cors_allowed_origin)
+            .field("rate_limit_enabled", &self.rate_limit_enabled)
+            .field("rate_limit_trust_forwarded", &self.rate_limit_trust_forwarded)
+            .field("rate_limit_login_ip", &self.rate_limit_login_ip)
+            .field("rate_limit_login_account", &self.rate_limit_login_account)
+            .field("rate_limit_refresh_ip", &self.rate_limit_refresh_ip)
+            .field("rate_limit_refresh_account", &self.rate_limit_refresh_account)
             .field("server_port", &self.server_port)
             .field("dev_fallback_keys", &self.dev_fallback_keys)
+            .field("user_repository", &self.user_repository)
+            .field("database_url", &self.database_url)
+            .field("seed_user_email", &self.seed_user_email)
+            .field("seed_user_roles", &self.seed_user_roles)
+            .field("seed_user_permissions", &self.seed_user_permissions)
+            .field("seed_client_id", &self.seed_client_id)
+            .field("seed_client_scopes", &self.seed_client_scopes)
             .finish()
     }
 }
@@ -45,7 +153,7 @@ impl fmt::Debug for AppConfig {
 static CONFIG: OnceLock<AppConfig> = OnceLock::new();
 
 pub fn get_config() -> &'static AppConfig {
-    CONFIG.get_or_init(|| load_config())
+    CONFIG.get_or_init(load_config)
 }
 
 fn load_config() -> AppConfig {
@@ -56,46 +164,136 @@ fn load_config() -> AppConfig {
     let iss = env_str("TOKEN_ISS", "apsara-devkit");
     let aud = env_str("TOKEN_AUD", "web");
 
+    // What verification accepts; tokens are always issued with TOKEN_ISS / TOKEN_AUD
+    let accepted_issuers = env_list("TOKEN_ACCEPTED_ISSUERS", &[&iss]);
+    let accepted_audiences = env_list("TOKEN_ACCEPTED_AUDIENCES", &[&aud]);
+    let required_claims = env_list("TOKEN_REQUIRED_CLAIMS", &["sub", "jti"]);
+    let token_leeway_secs = env_i64("TOKEN_LEEWAY_SECS", 0);
+    let max_token_age_secs = env::var("TOKEN_MAX_AGE_SECS").ok().and_then(|v| v.parse().ok());
+    if accepted_issuers.is_empty() || accepted_audiences.is_empty() {
+        panic!("TOKEN_ACCEPTED_ISSUERS and TOKEN_ACCEPTED_AUDIENCES must not be empty");
+    }
+
     let access_ttl_min = env_i64("ACCESS_TOKEN_TTL_MIN", 15);
     let refresh_ttl_days = env_i64("REFRESH_TOKEN_TTL_DAYS", 7);
 
+    // Token exchange: lifetime of issued tokens, and the permission needed to
+    // impersonate a user (admins with "*" hold it)
+    let token_exchange_ttl_min = env_i64("TOKEN_EXCHANGE_TTL_MIN", 5);
+    let impersonation_permission = env_str("IMPERSONATION_PERMISSION", "users:impersonate");
+
+    // TOTP: the issuer label shown in authenticator apps, and how many 30s steps of
+    // clock drift either side of now are accepted
+    let totp_issuer = env_str("TOTP_ISSUER", &iss);
+    let totp_skew_steps = env_i64("TOTP_SKEW_STEPS", 1);
+    let mfa_challenge_ttl_secs = env_i64("MFA_CHALLENGE_TTL_SECS", 300);
+    let recovery_code_count = env_u32("RECOVERY_CODE_COUNT", 10) as usize;
+
+    // API keys: lifetime when none is requested, and the longest allowed
+    let api_key_default_ttl_days = env_i64("API_KEY_DEFAULT_TTL_DAYS", 90);
+    let api_key_max_ttl_days = env_i64("API_KEY_MAX_TTL_DAYS", 365);
+
+    // OpenID Connect sign-in: one OIDC_<NAME>_* block per provider named in OIDC_PROVIDERS
+    let oidc_providers = env_list("OIDC_PROVIDERS", &[])
+        .iter()
+        .map(|name| load_oidc_provider(name))
+        .collect();
+    let oidc_post_login_redirect = env::var("OIDC_POST_LOGIN_REDIRECT").ok().filter(|v| !v.is_empty());
+    let oidc_auto_create_users = env_bool("OIDC_AUTO_CREATE_USERS", true);
+    let oidc_link_verified_email = env_bool("OIDC_LINK_VERIFIED_EMAIL", false);
+    let oidc_default_roles = env_list("OIDC_DEFAULT_ROLES", &["user"]);
+    let oidc_http_timeout_secs = env_u32("OIDC_HTTP_TIMEOUT_SECS", 10) as u64;
+    // "curl" (https via the system curl) or "plain" (http only, for a local mock IdP)
+    let oidc_http_client = env_str("OIDC_HTTP_CLIENT", "curl");
+    let oidc_curl_path = env_str("OIDC_CURL_PATH", "curl");
+
+    // How long consumers of /api/auth/keys may cache the key set
+    let keys_cache_max_age_secs = env_u32("KEYS_CACHE_MAX_AGE_SECS", 300);
+
     let cookie_secure = env_bool("COOKIE_SECURE", false);
     let cookie_domain = env_str("COOKIE_DOMAIN", "localhost");
     let cookie_path = env_str("COOKIE_PATH", "/");
     let refresh_cookie_name = env_str("REFRESH_COOKIE_NAME", "refresh_token");
 
     let cors_allowed_origin = env_str("CORS_ALLOWED_ORIGIN", "http://localhost:1111");
+
+    // Token buckets for credential endpoints and /refresh, per client IP and per account
+    let rate_limit_enabled = env_bool("RATE_LIMIT_ENABLED", true);
+    let rate_limit_trust_forwarded = env_bool("RATE_LIMIT_TRUST_FORWARDED", false);
+    let rate_limit_login_ip = env_bucket("RATE_LIMIT_LOGIN_IP", 20, 10);
+    let rate_limit_login_account = env_bucket("RATE_LIMIT_LOGIN_ACCOUNT", 5, 5);
+    let rate_limit_refresh_ip = env_bucket("RATE_LIMIT_REFRESH_IP", 60, 30);
+    let rate_limit_refresh_account = env_bucket("RATE_LIMIT_REFRESH_ACCOUNT", 20, 10);
     let server_port = env_u16("SERVER_PORT", 4444);
+    // Public base URL OIDC callbacks are built from
+    let oidc_redirect_base_url = env_str("OIDC_REDIRECT_BASE_URL", &format!("http://localhost:{server_port}"));
+
+    // Self-service signup, and whether password login waits for a verified email
+    let signup_enabled = env_bool("SIGNUP_ENABLED", true);
+    let signup_default_roles = env_list("SIGNUP_DEFAULT_ROLES", &["user"]);
+    let require_verified_email = env_bool("REQUIRE_VERIFIED_EMAIL", false);
+    let email_verification_ttl_secs = env_i64("EMAIL_VERIFICATION_TTL_SECS", 86_400);
+    let password_reset_ttl_secs = env_i64("PASSWORD_RESET_TTL_SECS", 1_800);
+    let magic_link_ttl_secs = env_i64("MAGIC_LINK_TTL_SECS", 900);
+
+    // Outgoing mail. Links in it point at the frontend, which posts the token back
+    let mail_link_base_url = env_str("MAIL_LINK_BASE_URL", &cors_allowed_origin);
+    let mail_transport = env_str("MAIL_TRANSPORT", "stdout");
+    let mail_from = env_str("MAIL_FROM", "no-reply@localhost");
+    let mail_file_dir = env_str("MAIL_FILE_DIR", "mail");
+    let smtp_host = env_str("SMTP_HOST", "localhost");
+    let smtp_port = env_u16("SMTP_PORT", 1025);
+    let smtp_username = env::var("SMTP_USERNAME").ok().filter(|v| !v.is_empty());
+    let smtp_password = env::var("SMTP_PASSWORD").ok().filter(|v| !v.is_empty());
+    let smtp_timeout_secs = env_u32("SMTP_TIMEOUT_SECS", 10) as u64;
+
+    // "sqlite" (default) or "memory"
+    let user_repository = env_str("USER_REPOSITORY", "sqlite");
+    let database_url = env_str("DATABASE_URL", "sqlite://rust-backend.db");
+
+    // Optional bootstrap account; skipped when no password is configured
+    let seed_user_email = env_str("SEED_USER_EMAIL", "admin@localhost");
+    let seed_user_password = env::var("SEED_USER_PASSWORD").ok().filter(|v| !v.is_empty());
+    let seed_user_roles = env_list("SEED_USER_ROLES", &["admin"]);
+    let seed_user_permissions = env_list("SEED_USER_PERMISSIONS", &["*"]);
 
-    // Keys: require both private and public if provided; otherwise generate pair in dev
+    // Optional bootstrap OAuth client for service-to-service tokens
+    let seed_client_id = env::var("SEED_CLIENT_ID").ok().filter(|v| !v.is_empty());
+    let seed_client_secret = env::var("SEED_CLIENT_SECRET").ok().filter(|v| !v.is_empty());
+    // Client secrets are stored as plain SHA-256 digests, which only random secrets can afford
+    if seed_client_secret.as_ref().is_some_and(|s| s.len() < 32) {
+        panic!("SEED_CLIENT_SECRET must be at least 32 random characters");
+    }
+    let seed_client_scopes = env_list("SEED_CLIENT_SCOPES", &[]);
+
+    // Keys: a keyring file wins; otherwise a single active pair from env; otherwise generate in dev
+    let keyring_file = env::var("ACCESS_KEYRING_FILE").ok().filter(|v| !v.is_empty());
     let priv_env = env::var("ACCESS_PRIVATE_KEY_BASE64").ok().filter(|v| !v.is_empty());
     let pub_env = env::var("ACCESS_PUBLIC_KEY_BASE64").ok().filter(|v| !v.is_empty());
 
-    let (signing_key, verifying_key) = match (priv_env, pub_env) {
-        (Some(sk_b64), Some(pk_b64)) => {
-            let sk_bytes = BASE64.decode(sk_b64).expect("ACCESS_PRIVATE_KEY_BASE64 must be valid base64");
-            let pk_bytes = BASE64.decode(pk_b64).expect("ACCESS_PUBLIC_KEY_BASE64 must be valid base64");
-            let sk = AsymmetricSecretKey::<V4>::try_from(sk_bytes.as_slice())
-                .expect("ACCESS_PRIVATE_KEY_BASE64 must be 32 bytes Ed25519 secret");
-            let pk = AsymmetricPublicKey::<V4>::try_from(pk_bytes.as_slice())
-                .expect("ACCESS_PUBLIC_KEY_BASE64 must be 32 bytes Ed25519 public");
-            (sk, pk)
+    let keyring = match (keyring_file, priv_env, pub_env) {
+        (Some(path), _, _) => load_keyring_file(&path),
+        (None, Some(sk_b64), Some(pk_b64)) => {
+            let sk = decode_secret_key("ACCESS_PRIVATE_KEY_BASE64", &sk_b64);
+            let pk = decode_public_key("ACCESS_PUBLIC_KEY_BASE64", &pk_b64);
+            Keyring::new(vec![KeyEntry::new(KeyStatus::Active, Some(sk), pk, None, None)])
+                .expect("access keyring")
         }
         _ if dev_fallback_keys => {
             println!("[auth] DEV_FALLBACK_KEYS enabled; generating ephemeral v4.public keypair");
             let kp = AsymmetricKeyPair::<V4>::generate().expect("keypair generation");
-            (kp.secret, kp.public)
+            Keyring::new(vec![KeyEntry::new(KeyStatus::Active, Some(kp.secret), kp.public, None, None)])
+                .expect("access keyring")
         }
         _ => panic!(
-            "ACCESS_PRIVATE_KEY_BASE64 and ACCESS_PUBLIC_KEY_BASE64 must both be set, or DEV_FALLBACK_KEYS=true for ephemeral generation"
+            "ACCESS_KEYRING_FILE, or ACCESS_PRIVATE_KEY_BASE64 and ACCESS_PUBLIC_KEY_BASE64, must be set, or DEV_FALLBACK_KEYS=true for ephemeral generation"
         ),
     };
 
     let refresh_key = match env::var("REFRESH_SYMMETRIC_KEY_BASE64") {
-        Ok(v) if !v.is_empty() => {
-            let bytes = BASE64.decode(v).expect("REFRESH_SYMMETRIC_KEY_BASE64 must be valid base64");
-            SymmetricKey::<V4>::try_from(bytes.as_slice()).expect("REFRESH_SYMMETRIC_KEY_BASE64 must be 32 bytes")
-        }
+        Ok(v) if !v.is_empty() => parse_symmetric_key(&v).unwrap_or_else(|e| {
+            panic!("REFRESH_SYMMETRIC_KEY_BASE64 is not a usable v4.local key ({e})")
+        }),
         _ if dev_fallback_keys => {
             println!("[auth] DEV_FALLBACK_KEYS enabled; generating ephemeral v4.local symmetric key");
             SymmetricKey::<V4>::generate().expect("symmetric key generation")
@@ -105,24 +303,159 @@ fn load_config() -> AppConfig {
         ),
     };
 
+    let mfa_key = match env::var("MFA_SYMMETRIC_KEY_BASE64") {
+        Ok(v) if !v.is_empty() => parse_symmetric_key(&v).unwrap_or_else(|e| {
+            panic!("MFA_SYMMETRIC_KEY_BASE64 is not a usable v4.local key ({e})")
+        }),
+        _ if dev_fallback_keys => {
+            println!("[auth] DEV_FALLBACK_KEYS enabled; generating ephemeral v4.local MFA key");
+            SymmetricKey::<V4>::generate().expect("symmetric key generation")
+        }
+        _ => panic!(
+            "MFA_SYMMETRIC_KEY_BASE64 not set and DEV_FALLBACK_KEYS=false. Provide a base64-encoded 32-byte key."
+        ),
+    };
+
     AppConfig {
-        signing_key,
-        verifying_key,
+        keyring,
         refresh_key,
+        mfa_key,
         iss,
         aud,
+        accepted_issuers,
+        accepted_audiences,
+        required_claims,
+        token_leeway_secs,
+        max_token_age_secs,
         access_ttl_min,
+        keys_cache_max_age_secs,
         refresh_ttl_days,
+        token_exchange_ttl_min,
+        impersonation_permission,
+        totp_issuer,
+        totp_skew_steps,
+        mfa_challenge_ttl_secs,
+        recovery_code_count,
+        api_key_default_ttl_days,
+        api_key_max_ttl_days,
+        signup_enabled,
+        signup_default_roles,
+        require_verified_email,
+        email_verification_ttl_secs,
+        password_reset_ttl_secs,
+        magic_link_ttl_secs,
+        mail_link_base_url,
+        mail_transport,
+        mail_from,
+        mail_file_dir,
+        smtp_host,
+        smtp_port,
+        smtp_username,
+        smtp_password,
+        smtp_timeout_secs,
+        oidc_providers,
+        oidc_redirect_base_url,
+        oidc_post_login_redirect,
+        oidc_auto_create_users,
+        oidc_link_verified_email,
+        oidc_default_roles,
+        oidc_http_timeout_secs,
+        oidc_http_client,
+        oidc_curl_path,
         cookie_secure,
         cookie_domain,
         cookie_path,
         refresh_cookie_name,
         cors_allowed_origin,
+        rate_limit_enabled,
+        rate_limit_trust_forwarded,
+        rate_limit_login_ip,
+        rate_limit_login_account,
+        rate_limit_refresh_ip,
+        rate_limit_refresh_account,
         server_port,
         dev_fallback_keys,
+        user_repository,
+        database_url,
+        seed_user_email,
+        seed_user_password,
+        seed_user_roles,
+        seed_user_permissions,
+        seed_client_id,
+        seed_client_secret,
+        seed_client_scopes,
+    }
+}
+
+/// One entry of the JSON array in `ACCESS_KEYRING_FILE`. `public_key` may be omitted
+/// when `private_key` is present; verify-only keys need only `public_key`.
+#[derive(Deserialize)]
+struct KeyringFileEntry {
+    status: KeyStatus,
+    #[serde(default)]
+    private_key: Option<String>,
+    #[serde(default)]
+    public_key: Option<String>,
+    #[serde(default)]
+    not_before: Option<i64>,
+    #[serde(default)]
+    not_after: Option<i64>,
+}
+
+fn load_keyring_file(path: &str) -> Keyring {
+    let raw = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("ACCESS_KEYRING_FILE {path} could not be read: {e}"));
+    let entries: Vec<KeyringFileEntry> = serde_json::from_str(&raw)
+        .unwrap_or_else(|e| panic!("ACCESS_KEYRING_FILE {path} is not a valid keyring: {e}"));
+
+    let keys = entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let name = format!("ACCESS_KEYRING_FILE[{i}]");
+            let secret = entry
+                .private_key
+                .as_deref()
+                .map(|v| decode_secret_key(&format!("{name}.private_key"), v));
+            let public = match (&entry.public_key, &secret) {
+                (Some(v), _) => decode_public_key(&format!("{name}.public_key"), v),
+                (None, Some(sk)) => AsymmetricPublicKey::<V4>::try_from(sk)
+                    .unwrap_or_else(|e| panic!("{name}: could not derive public key: {e}")),
+                (None, None) => panic!("{name}: needs private_key or public_key"),
+            };
+            KeyEntry::new(entry.status, secret, public, entry.not_before, entry.not_after)
+        })
+        .collect();
+
+    Keyring::new(keys).unwrap_or_else(|e| panic!("ACCESS_KEYRING_FILE {path}: {e}"))
+}
+
+fn load_oidc_provider(name: &str) -> OidcProviderConfig {
+    let name = name.to_ascii_lowercase();
+    let prefix = format!("OIDC_{}", name.to_ascii_uppercase().replace('-', "_"));
+    let required = |suffix: &str| {
+        env::var(format!("{prefix}_{suffix}"))
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| panic!("{prefix}_{suffix} must be set for OIDC provider {name}"))
+    };
+    OidcProviderConfig {
+        issuer: required("ISSUER"),
+        client_id: required("CLIENT_ID"),
+        client_secret: env::var(format!("{prefix}_CLIENT_SECRET")).ok().filter(|v| !v.is_empty()),
+        scopes: env_str(&format!("{prefix}_SCOPES"), "openid email profile"),
+        name,
     }
 }
 
+fn decode_secret_key(name: &str, value: &str) -> AsymmetricSecretKey<V4> {
+    parse_secret_key(value).unwrap_or_else(|e| panic!("{name} is not a usable Ed25519 secret key ({e})"))
+}
+
+fn decode_public_key(name: &str, value: &str) -> AsymmetricPublicKey<V4> {
+    parse_public_key(value).unwrap_or_else(|e| panic!("{name} is not a usable Ed25519 public key ({e})"))
+}
+
 fn env_str(key: &str, default: &str) -> String {
     env::var(key).unwrap_or_else(|_| default.to_string())
 }
@@ -134,6 +467,17 @@ fn env_bool(key: &str, default: bool) -> bool {
     }
 }
 
+fn env_list(key: &str, default: &[&str]) -> Vec<String> {
+    match env::var(key) {
+        Ok(v) => v
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => default.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
 fn env_i64(key: &str, default: i64) -> i64 {
     match env::var(key) {
         Ok(v) => v.parse().unwrap_or(default),
@@ -141,6 +485,21 @@ fn env_i64(key: &str, default: i64) -> i64 {
     }
 }
 
+fn env_u32(key: &str, default: u32) -> u32 {
+    match env::var(key) {
+        Ok(v) => v.parse().unwrap_or(default),
+        Err(_) => default,
+    }
+}
+
+/// Reads `<prefix>_BURST` and `<prefix>_PER_MIN`.
+fn env_bucket(prefix: &str, burst: u32, per_minute: u32) -> BucketLimit {
+    BucketLimit {
+        burst: env_u32(&format!("{prefix}_BURST"), burst),
+        per_minute: env_u32(&format!("{prefix}_PER_MIN"), per_minute).max(1),
+    }
+}
+
 fn env_u16(key: &str, default: u16) -> u16 {
     match env::var(key) {
         Ok(v) => v.parse().unwrap_or(default),
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::identities::{Identity, IdentityRepository};
+
+/// Process-local repository; state is lost on restart. Meant for tests and quick demos.
+#[derive(Debug, Default)]
+pub struct InMemoryIdentityRepository {
+    /// (provider, subject) -> identity
+    identities: RwLock<HashMap<(String, String), Identity>>,
+}
+
+impl InMemoryIdentityRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for InMemoryIdentityRepository {
+    async fn find(&self, provider: &str, subject: &str) -> AuthResult<Option<Identity>> {
+        let identities = self.identities.read().expect("identity repository lock");
+        Ok(identities.get(&(provider.to_string(), subject.to_string())).cloned())
+    }
+
+    async fn create(&self, identity: Identity) -> AuthResult<Identity> {
+        let mut identities = self.identities.write().expect("identity repository lock");
+        let key = (identity.provider.clone(), identity.subject.clone());
+        if identities.contains_key(&key) {
+            return Err(AuthError::UserAlreadyExists);
+        }
+        identities.insert(key, identity.clone());
+        Ok(identity)
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::events::{self, AuthEvent};
+use crate::auth::password::hash_password;
+use crate::config::get_config;
+use crate::oidc::id_token::IdTokenClaims;
+use crate::storage::StorageBackend;
+use crate::users::{User, UserRepository};
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemoryIdentityRepository;
+pub use sqlite::SqliteIdentityRepository;
+
+/// A provider account (`iss` + `sub` of its ID tokens) linked to a local user.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// Configured provider name, e.g. `google`
+    pub provider: String,
+    /// The provider's stable `sub` claim; emails can change, this cannot
+    pub subject: String,
+    pub user_id: String,
+    /// Email the provider asserted when the link was made, for display only
+    pub email: Option<String>,
+    pub created_at: i64,
+}
+
+impl Identity {
+    pub fn new(provider: &str, subject: &str, user_id: &str, email: Option<String>) -> Self {
+        Self {
+            provider: provider.to_string(),
+            subject: subject.to_string(),
+            user_id: user_id.to_string(),
+            email,
+            created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait IdentityRepository: Send + Sync {
+    async fn find(&self, provider: &str, subject: &str) -> AuthResult<Option<Identity>>;
+
+    /// Links the identity. Fails with `UserAlreadyExists` if it is already linked.
+    async fn create(&self, identity: Identity) -> AuthResult<Identity>;
+}
+
+/// Builds the repository for the configured storage backend.
+pub fn repository(storage: &StorageBackend) -> web::Data<dyn IdentityRepository> {
+    let repo: Arc<dyn IdentityRepository> = match storage {
+        StorageBackend::Memory => Arc::new(InMemoryIdentityRepository::new()),
+        StorageBackend::Sqlite(pool) => Arc::new(SqliteIdentityRepository::new(pool.clone())),
+    };
+    web::Data::from(repo)
+}
+
+/// Finds or provisions the local account for a verified ID token from `provider`.
+///
+/// An identity already linked wins. Otherwise, with `OIDC_LINK_VERIFIED_EMAIL`, an
+/// account with the same provider-verified email is linked; unverified emails are
+/// never trusted for this, as anyone can put any address on a provider account.
+/// Otherwise, with `OIDC_AUTO_CREATE_USERS`, a new account without a usable
+/// password is created, again only for a provider-verified email: an account made
+/// for someone else's address would be theirs to take over by a password reset.
+pub async fn resolve_user(
+    identities: &dyn IdentityRepository,
+    users: &dyn UserRepository,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> AuthResult<User> {
+    let cfg = get_config();
+
+    if let Some(identity) = identities.find(provider, &claims.sub).await? {
+        return match users.find_by_id(&identity.user_id).await? {
+            Some(user) if !user.disabled => Ok(user),
+            _ => Err(AuthError::InvalidCredentials),
+        };
+    }
+
+    let email = claims.email.as_deref().filter(|e| !e.is_empty());
+    let existing = match email {
+        Some(email) => users.find_by_email(email).await?,
+        None => None,
+    };
+
+    let (user, created_user) = match existing {
+        Some(user) if cfg.oidc_link_verified_email && claims.email_verified => {
+            if user.disabled {
+                return Err(AuthError::InvalidCredentials);
+            }
+            (user, false)
+        }
+        // Taking over an account by its email needs the explicit opt-in above
+        Some(_) => return Err(AuthError::OidcAccountNotLinked),
+        None if cfg.oidc_auto_create_users && claims.email_verified => {
+            let email = email.ok_or(AuthError::OidcAccountNotLinked)?.to_string();
+            (create_user(users, email).await?, true)
+        }
+        None => return Err(AuthError::OidcAccountNotLinked),
+    };
+
+    identities
+        .create(Identity::new(provider, &claims.sub, &user.id, claims.email.clone()))
+        .await?;
+    events::emit(AuthEvent::OidcIdentityLinked {
+        sub: user.id.clone(),
+        provider: provider.to_string(),
+        created_user,
+    });
+    Ok(user)
+}
+
+/// New account for an external identity with a provider-verified email. Its
+/// password is random and never shown, so password login stays closed until a
+/// reset sets one.
+async fn create_user(users: &dyn UserRepository, email: String) -> AuthResult<User> {
+    let cfg = get_config();
+
+    let mut password = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut password);
+    let password = URL_SAFE_NO_PAD.encode(password);
+    let password_hash = web::block(move || hash_password(&password))
+        .await
+        .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))??;
+
+    let mut user = User::new(email.clone(), email, password_hash, cfg.oidc_default_roles.clone(), Vec::new());
+    user.email_verified = true;
+    match users.create(user).await {
+        // Username taken by a different account than the email lookup found
+        Err(AuthError::UserAlreadyExists) => Err(AuthError::OidcAccountNotLinked),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::users::InMemoryUserRepository;
+
+    fn claims(email_verified: bool) -> IdTokenClaims {
+        IdTokenClaims {
+            sub: uuid::Uuid::new_v4().to_string(),
+            email: Some(format!("{}@example.com", uuid::Uuid::new_v4())),
+            email_verified,
+        }
+    }
+
+    #[actix_web::test]
+    async fn accounts_are_only_created_for_verified_emails() {
+        let identities = InMemoryIdentityRepository::new();
+        let users = InMemoryUserRepository::new();
+
+        let unverified = claims(false);
+        let refused = resolve_user(&identities, &users, "google", &unverified).await;
+        assert!(matches!(refused, Err(AuthError::OidcAccountNotLinked)));
+        assert!(users.find_by_email(unverified.email.as_deref().unwrap()).await.unwrap().is_none());
+
+        let verified = claims(true);
+        let user = resolve_user(&identities, &users, "google", &verified).await.unwrap();
+        assert_eq!(user.email, verified.email.unwrap());
+        assert!(user.email_verified);
+    }
+}
@@ -0,0 +1,64 @@
+use async_trait::async_trait;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::identities::{Identity, IdentityRepository};
+use crate::storage::storage_error;
+
+/// SQLite-backed repository over the pool opened by `storage::connect_sqlite`.
+#[derive(Debug, Clone)]
+pub struct SqliteIdentityRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteIdentityRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for SqliteIdentityRepository {
+    async fn find(&self, provider: &str, subject: &str) -> AuthResult<Option<Identity>> {
+        let row = sqlx::query(
+            "SELECT provider, subject, user_id, email, created_at FROM user_identities \
+             WHERE provider = ?1 AND subject = ?2",
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        row.as_ref().map(row_to_identity).transpose()
+    }
+
+    async fn create(&self, identity: Identity) -> AuthResult<Identity> {
+        sqlx::query(
+            "INSERT INTO user_identities (provider, subject, user_id, email, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(&identity.user_id)
+        .bind(&identity.email)
+        .bind(identity.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => AuthError::UserAlreadyExists,
+            _ => storage_error(e),
+        })?;
+        Ok(identity)
+    }
+}
+
+fn row_to_identity(row: &SqliteRow) -> AuthResult<Identity> {
+    Ok(Identity {
+        provider: row.try_get("provider").map_err(storage_error)?,
+        subject: row.try_get("subject").map_err(storage_error)?,
+        user_id: row.try_get("user_id").map_err(storage_error)?,
+        email: row.try_get("email").map_err(storage_error)?,
+        created_at: row.try_get("created_at").map_err(storage_error)?,
+    })
+}
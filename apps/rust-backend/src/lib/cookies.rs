@@ -6,13 +6,13 @@ use crate::config::get_config;
 
 pub fn set_refresh_cookie(resp: &mut HttpResponse, token: &str) {
     let cfg = get_config();
-    let mut cookie = Cookie::build(cfg.refresh_cookie_name.clone(), token.to_string())
+    let cookie = Cookie::build((cfg.refresh_cookie_name.clone(), token.to_string()))
         .path(cfg.cookie_path.clone())
         .http_only(true)
         .secure(cfg.cookie_secure)
         .same_site(SameSite::Lax)
         .domain(cfg.cookie_domain.clone())
-        .finish();
+        .build();
 
     resp.headers_mut().append(
         SET_COOKIE,
@@ -22,14 +22,51 @@ pub fn set_refresh_cookie(resp: &mut HttpResponse, token: &str) {
 
 pub fn clear_refresh_cookie(resp: &mut HttpResponse) {
     let cfg = get_config();
-    let mut cookie = Cookie::build(cfg.refresh_cookie_name.clone(), "")
+    let cookie = Cookie::build((cfg.refresh_cookie_name.clone(), ""))
         .path(cfg.cookie_path.clone())
         .http_only(true)
         .secure(cfg.cookie_secure)
         .same_site(SameSite::Lax)
         .domain(cfg.cookie_domain.clone())
         .max_age(time::Duration::seconds(-1))
-        .finish();
+        .build();
+
+    resp.headers_mut().append(
+        SET_COOKIE,
+        HeaderValue::from_str(&cookie.to_string()).expect("valid cookie"),
+    );
+}
+
+/// Cookie carrying the sealed OIDC flow state between the redirect to the
+/// provider and the callback. Lax so it survives the top-level redirect back.
+pub const OIDC_FLOW_COOKIE: &str = "oidc_flow";
+const OIDC_FLOW_COOKIE_PATH: &str = "/api/auth/oidc";
+
+pub fn set_oidc_flow_cookie(resp: &mut HttpResponse, sealed: &str, max_age_secs: i64) {
+    let cfg = get_config();
+    let cookie = Cookie::build((OIDC_FLOW_COOKIE, sealed.to_string()))
+        .path(OIDC_FLOW_COOKIE_PATH)
+        .http_only(true)
+        .secure(cfg.cookie_secure)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(max_age_secs))
+        .build();
+
+    resp.headers_mut().append(
+        SET_COOKIE,
+        HeaderValue::from_str(&cookie.to_string()).expect("valid cookie"),
+    );
+}
+
+pub fn clear_oidc_flow_cookie(resp: &mut HttpResponse) {
+    let cfg = get_config();
+    let cookie = Cookie::build((OIDC_FLOW_COOKIE, ""))
+        .path(OIDC_FLOW_COOKIE_PATH)
+        .http_only(true)
+        .secure(cfg.cookie_secure)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(-1))
+        .build();
 
     resp.headers_mut().append(
         SET_COOKIE,
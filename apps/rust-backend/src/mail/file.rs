@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use actix_web::web;
+use async_trait::async_trait;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+use crate::mail::{Email, Mailer};
+
+/// Development transport: writes each message to `<dir>/<timestamp>-<id>.eml`, or
+/// prints it when there is no directory. Nothing leaves the machine.
+#[derive(Debug, Clone)]
+pub struct FileMailer {
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: Some(dir.into()) }
+    }
+
+    pub fn stdout() -> Self {
+        Self { dir: None }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> AuthResult<()> {
+        let message = email.render(&get_config().mail_from)?;
+        let Some(dir) = self.dir.clone() else {
+            println!("[mail] to {}\n{}", email.to, message.replace("\r\n", "\n"));
+            return Ok(());
+        };
+
+        let name = format!(
+            "{}-{}.eml",
+            time::OffsetDateTime::now_utc().unix_timestamp(),
+            uuid::Uuid::new_v4()
+        );
+        let path = dir.join(name);
+        let written = path.clone();
+        web::block(move || {
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(&path, message)
+        })
+        .await
+        .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))?
+        .map_err(|e| AuthError::MailDelivery(format!("{}: {e}", written.display())))?;
+
+        println!("[mail] to {} written to {}", email.to, written.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use time::format_description::well_known::Rfc2822;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+
+pub mod file;
+pub mod smtp;
+
+pub use file::FileMailer;
+pub use smtp::SmtpMailer;
+
+/// A plain-text message to a single recipient.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// Renders the message as RFC 5322 text with CRLF line endings, ready for a
+    /// `.eml` file or the SMTP DATA command.
+    pub fn render(&self, from: &str) -> AuthResult<String> {
+        // Header values come partly from user input; a line break would let it add headers
+        if [from, &self.to, &self.subject].iter().any(|v| v.contains(['\r', '\n'])) {
+            return Err(AuthError::MailDelivery("line break in a header value".into()));
+        }
+        let date = time::OffsetDateTime::now_utc()
+            .format(&Rfc2822)
+            .map_err(|e| AuthError::Internal(format!("mail date: {e}")))?;
+        let domain = address(from).rsplit_once('@').map_or("localhost", |(_, d)| d);
+
+        let mut message = format!(
+            "Date: {date}\r\nFrom: {from}\r\nTo: {}\r\nSubject: {}\r\nMessage-ID: <{}@{domain}>\r\n\
+             MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: 8bit\r\n\r\n",
+            self.to,
+            encode_header(&self.subject),
+            uuid::Uuid::new_v4(),
+        );
+        for line in self.body.lines() {
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        Ok(message)
+    }
+}
+
+/// Outgoing mail transport. Handlers receive it as `web::Data<dyn Mailer>`;
+/// implementations deliver or fail, they do not queue.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> AuthResult<()>;
+}
+
+/// Builds the transport named by `MAIL_TRANSPORT`: "smtp", "file" or "stdout".
+pub fn from_config() -> web::Data<dyn Mailer> {
+    let cfg = get_config();
+    let mailer: Arc<dyn Mailer> = match cfg.mail_transport.as_str() {
+        "smtp" => {
+            // SmtpMailer would refuse them on every message; better not to start at all
+            if cfg.smtp_username.is_some() || cfg.smtp_password.is_some() {
+                panic!("SMTP_USERNAME and SMTP_PASSWORD need TLS, which the smtp transport lacks; relay through a local server instead");
+            }
+            Arc::new(SmtpMailer::new(&cfg.smtp_host, cfg.smtp_port, None, Duration::from_secs(cfg.smtp_timeout_secs)))
+        }
+        "file" => Arc::new(FileMailer::new(&cfg.mail_file_dir)),
+        _ => Arc::new(FileMailer::stdout()),
+    };
+    web::Data::from(mailer)
+}
+
+/// Bare address of a mailbox such as `Example <no-reply@example.com>`.
+pub fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+/// RFC 2047 encoded-word for non-ASCII header text.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", STANDARD.encode(value))
+    }
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+use crate::mail::{address, Email, Mailer};
+
+/// Longest reply line accepted from the server; RFC 5321 allows 512 octets
+const MAX_REPLY_LINE: usize = 1024;
+
+/// Minimal SMTP client over plain TCP: EHLO, then one message to one recipient.
+///
+/// There is no STARTTLS, so it is meant for a relay on the same host or network, or
+/// a local sink such as MailHog or `python -m aiosmtpd -n`. For the same reason it
+/// refuses to authenticate: AUTH PLAIN is only base64, and would hand the password
+/// to anyone on the path. Delivering over the internet needs a `Mailer` backed by a
+/// TLS-capable library.
+#[derive(Debug, Clone)]
+pub struct SmtpMailer {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+    timeout: Duration,
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, port: u16, credentials: Option<(String, String)>, timeout: Duration) -> Self {
+        Self { host: host.to_string(), port, credentials, timeout }
+    }
+
+    async fn deliver(&self, from: &str, to: &str, message: &str) -> AuthResult<()> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|e| self.error(format!("connect: {e}")))?;
+        let mut conn = BufReader::new(stream);
+
+        self.expect(&mut conn, 220).await?;
+        self.command(&mut conn, "EHLO localhost", 250).await?;
+        if self.credentials.is_some() {
+            let _ = self.command(&mut conn, "QUIT", 221).await;
+            return Err(self.error("refusing to send credentials without TLS".into()));
+        }
+        self.command(&mut conn, &format!("MAIL FROM:<{}>", address(from)), 250).await?;
+        self.command(&mut conn, &format!("RCPT TO:<{}>", address(to)), 250).await?;
+        self.command(&mut conn, "DATA", 354).await?;
+
+        // Lines starting with a dot are doubled so none ends the data early
+        let mut data = String::with_capacity(message.len() + 8);
+        for line in message.split_inclusive("\r\n") {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+        }
+        data.push_str(".\r\n");
+        self.write(&mut conn, &data).await?;
+        self.expect(&mut conn, 250).await?;
+
+        // The message is accepted at this point; a failing QUIT changes nothing
+        let _ = self.command(&mut conn, "QUIT", 221).await;
+        Ok(())
+    }
+
+    async fn command(&self, conn: &mut BufReader<TcpStream>, line: &str, expected: u16) -> AuthResult<()> {
+        self.write(conn, &format!("{line}\r\n")).await?;
+        self.expect(conn, expected).await
+    }
+
+    async fn write(&self, conn: &mut BufReader<TcpStream>, data: &str) -> AuthResult<()> {
+        conn.get_mut()
+            .write_all(data.as_bytes())
+            .await
+            .map_err(|e| self.error(format!("write: {e}")))
+    }
+
+    /// Reads one possibly multi-line reply (`250-...` continued up to `250 ...`)
+    /// and checks its code.
+    async fn expect(&self, conn: &mut BufReader<TcpStream>, expected: u16) -> AuthResult<()> {
+        loop {
+            let mut line = String::new();
+            let read = conn
+                .read_line(&mut line)
+                .await
+                .map_err(|e| self.error(format!("read: {e}")))?;
+            if read == 0 || line.len() > MAX_REPLY_LINE {
+                return Err(self.error("connection closed or reply too long".into()));
+            }
+
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+            if code != Some(expected) {
+                return Err(self.error(format!("expected {expected}, got {line:?}")));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    fn error(&self, detail: String) -> AuthError {
+        AuthError::MailDelivery(format!("smtp {}:{}: {detail}", self.host, self.port))
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> AuthResult<()> {
+        let from = &get_config().mail_from;
+        let message = email.render(from)?;
+        tokio::time::timeout(self.timeout, self.deliver(from, &email.to, &message))
+            .await
+            .map_err(|_| self.error("timed out".into()))??;
+        println!("[mail] to {} sent via {}:{}", email.to, self.host, self.port);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Plays the server side of one session, answering each command as a sink
+    /// would, and hands back everything the client sent.
+    async fn sink() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = BufReader::new(stream);
+            let mut transcript = String::new();
+            let mut in_data = false;
+            conn.get_mut().write_all(b"220 sink ready\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if conn.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let reply: &[u8] = if in_data {
+                    if line != ".\r\n" {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-sink\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    conn.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                conn.get_mut().write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    #[actix_web::test]
+    async fn delivers_with_dot_stuffing() {
+        let (port, server) = sink().await;
+        let mailer = SmtpMailer::new("127.0.0.1", port, None, Duration::from_secs(5));
+
+        let message = "Subject: hi\r\n\r\n.leading dot\r\n.\r\nlast\r\n";
+        mailer
+            .deliver("App <no-reply@example.com>", "user@example.com", message)
+            .await
+            .unwrap();
+
+        let transcript = server.await.unwrap();
+        assert_eq!(
+            transcript,
+            "EHLO localhost\r\n\
+             MAIL FROM:<no-reply@example.com>\r\n\
+             RCPT TO:<user@example.com>\r\n\
+             DATA\r\n\
+             Subject: hi\r\n\r\n..leading dot\r\n..\r\nlast\r\n.\r\n\
+             QUIT\r\n"
+        );
+    }
+
+    #[actix_web::test]
+    async fn refuses_to_send_credentials_without_tls() {
+        let (port, server) = sink().await;
+        let credentials = Some(("user".to_string(), "secret".to_string()));
+        let mailer = SmtpMailer::new("127.0.0.1", port, credentials, Duration::from_secs(5));
+
+        let result = mailer.deliver("no-reply@example.com", "user@example.com", "\r\n").await;
+        assert!(matches!(result, Err(AuthError::MailDelivery(e)) if e.contains("without TLS")));
+
+        let transcript = server.await.unwrap();
+        assert_eq!(transcript, "EHLO localhost\r\nQUIT\r\n");
+    }
+}
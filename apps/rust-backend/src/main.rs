@@ -1,13 +1,28 @@
+// `src/lib/` is a plain module directory, not a library target
+#![allow(special_module_name)]
+
 use actix_web::{get, post, HttpResponse, Responder, App, HttpServer, web};
 use actix_cors::Cors;
 use actix_web::http::header;
 use actix_web_httpauth::middleware::HttpAuthentication;
 
+use crate::middleware::rbac::{RequireRole, RequireUser};
+
 mod config;
+mod api_keys;
 mod auth;
+mod clients;
 mod middleware;
 mod routes;
 mod lib;
+mod identities;
+mod mail;
+mod oidc;
+mod rate_limit;
+mod recovery;
+mod sessions;
+mod storage;
+mod users;
 
 #[get("/")]
 async fn hello() -> impl Responder {
@@ -27,32 +42,149 @@ async fn manual_hello() -> impl Responder {
 async fn main() -> std::io::Result<()> {
     let cfg = config::get_config();
 
+    let storage = storage::StorageBackend::from_config().await.expect("storage backend");
+    let user_repo = users::repository(&storage);
+    let session_repo = sessions::repository(&storage);
+    let recovery_repo = recovery::repository(&storage);
+    let identity_repo = identities::repository(&storage);
+    let client_repo = clients::repository(&storage);
+    let api_key_repo = api_keys::repository(&storage);
+    let oidc_client = web::Data::new(oidc::OidcClient::from_config());
+    let mailer = mail::from_config();
+    let rate_limit_store = rate_limit::store();
+    users::seed_from_config(user_repo.get_ref()).await.expect("seed user");
+    clients::seed_from_config(client_repo.get_ref()).await.expect("seed client");
+
     HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin(&cfg.cors_allowed_origin)
             .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-            .allowed_headers(vec![header::AUTHORIZATION, header::CONTENT_TYPE])
+            .allowed_headers(vec![
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                header::HeaderName::from_static(middleware::auth::API_KEY_HEADER),
+            ])
             .supports_credentials();
 
-        let bearer = HttpAuthentication::bearer(middleware::auth::validator);
+        let bearer = HttpAuthentication::with_fn(middleware::auth::validator);
 
         App::new()
+            .app_data(user_repo.clone())
+            .app_data(session_repo.clone())
+            .app_data(recovery_repo.clone())
+            .app_data(identity_repo.clone())
+            .app_data(client_repo.clone())
+            .app_data(api_key_repo.clone())
+            .app_data(oidc_client.clone())
+            .app_data(mailer.clone())
+            .app_data(rate_limit_store.clone())
+            .app_data(web::JsonConfig::default().error_handler(|err, _req| {
+                auth::error::AuthError::InvalidRequest(err.to_string()).into()
+            }))
+            // Forms are only taken by the OAuth endpoints, which answer in the OAuth shape
+            .app_data(web::FormConfig::default().error_handler(|err, _req| {
+                auth::error::AuthError::InvalidRequest(err.to_string()).into_oauth_error()
+            }))
             .wrap(cors)
+            // Outermost so every error, including middleware rejections, carries the id
+            .wrap(middleware::request_id::RequestId)
             .service(
                 web::scope("/api")
                     // Public auth endpoints
                     .service(
                         web::scope("/auth")
+                            .service(routes::auth::register)
                             .service(routes::auth::login)
                             .service(routes::auth::refresh)
-                            .service(routes::auth::logout),
+                            .service(routes::auth::logout)
+                            .service(routes::token::issue_token)
+                            .service(routes::token::introspect)
+                            .service(routes::keys::keys)
+                            .service(
+                                web::scope("/email")
+                                    .service(routes::email::verify_email)
+                                    .service(routes::email::resend_verification),
+                            )
+                            .service(
+                                web::scope("/magic-link")
+                                    .service(routes::magic_link::request_magic_link)
+                                    .service(routes::magic_link::consume_magic_link),
+                            )
+                            .service(
+                                web::scope("/password")
+                                    .service(routes::password::forgot_password)
+                                    .service(routes::password::reset_password),
+                            )
+                            .service(
+                                web::scope("/mfa")
+                                    .service(routes::mfa::verify_mfa)
+                                    .service(
+                                        web::scope("/totp")
+                                            .wrap(RequireUser)
+                                            .wrap(bearer.clone())
+                                            .service(routes::mfa::enroll_totp)
+                                            .service(routes::mfa::confirm_totp)
+                                            .service(routes::mfa::disable_totp),
+                                    ),
+                            )
+                            .service(
+                                web::scope("/recovery")
+                                    .service(routes::recovery::recovery_login)
+                                    .service(
+                                        web::scope("/codes")
+                                            .wrap(RequireUser)
+                                            .wrap(bearer.clone())
+                                            .service(routes::recovery::regenerate_recovery_codes)
+                                            .service(routes::recovery::recovery_codes_status),
+                                    ),
+                            )
+                            .service(
+                                web::scope("/oidc")
+                                    .service(routes::oidc::authorize)
+                                    .service(routes::oidc::callback),
+                            )
+                            .service(
+                                web::scope("/sessions")
+                                    .wrap(RequireUser)
+                                    .wrap(bearer.clone())
+                                    .service(routes::sessions::list_sessions)
+                                    .service(routes::sessions::delete_other_sessions)
+                                    .service(routes::sessions::delete_session),
+                            )
+                            .service(
+                                web::scope("/api-keys")
+                                    .wrap(RequireUser)
+                                    .wrap(bearer.clone())
+                                    .service(routes::api_keys::create_api_key)
+                                    .service(routes::api_keys::list_api_keys)
+                                    .service(routes::api_keys::delete_api_key),
+                            ),
+                    )
+                    // OAuth client registry, for administrators
+                    .service(
+                        web::scope("/admin/clients")
+                            .wrap(RequireRole("admin"))
+                            .wrap(bearer.clone())
+                            .service(routes::clients::create_client)
+                            .service(routes::clients::list_clients)
+                            .service(routes::clients::delete_client),
+                    )
+                    // Account administration
+                    .service(
+                        web::scope("/admin/users")
+                            .wrap(RequireRole("admin"))
+                            .wrap(bearer.clone())
+                            .service(routes::users::disable_user),
                     )
                     // Protected endpoints under /api/** (including /api/me)
                     .service(
                         web::scope("")
                             .wrap(bearer)
                             .service(routes::auth::me)
-                            .service(routes::protected::health),
+                            .service(routes::protected::health)
+                            .service(routes::protected::admin_health)
+                            .service(routes::protected::staff_health)
+                            .service(routes::protected::health_details),
                     ),
             )
             .service(hello)
@@ -1,16 +1,78 @@
-use actix_web::{dev::ServiceRequest, Error};
+use actix_web::{dev::ServiceRequest, web, Error, HttpMessage};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 
-use crate::auth::token::verify_access_token;
+use crate::api_keys::{authenticate_api_key, is_api_key, ApiKeyRepository};
 use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::token::{is_access_token_revoked, verify_access_token};
+use crate::middleware::request_id::current_request_id;
+use crate::users::UserRepository;
 
-pub async fn validator(mut req: ServiceRequest, credentials: BearerAuth) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let token = credentials.token();
-    match verify_access_token(token) {
+/// Alternative to `Authorization: Bearer ak_...` for clients that cannot set it
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Takes `Option<BearerAuth>` (via `HttpAuthentication::with_fn`) so a missing
+/// header is reported through `AuthError` like every other failure. Rejections
+/// carry an RFC 6750 `WWW-Authenticate` challenge, so clients can tell an expired
+/// token (refresh) from an invalid one (log in again).
+///
+/// API keys are accepted in the same place, as a bearer credential starting with
+/// `ak_` or in the `X-API-Key` header, and yield the same `AuthenticatedUser`.
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: Option<BearerAuth>,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let api_key = match &credentials {
+        Some(bearer) if is_api_key(bearer.token()) => Some(bearer.token().to_string()),
+        Some(_) => None,
+        None => req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+    };
+    if let Some(key) = api_key {
+        return match api_key_user(&req, &key).await {
+            Ok(user) => {
+                req.extensions_mut().insert::<AuthenticatedUser>(user);
+                Ok(req)
+            }
+            Err(e) => Err((e.into_bearer_error(), req)),
+        };
+    }
+
+    let Some(credentials) = credentials else {
+        return Err((AuthError::MissingCredentials.into_bearer_error(), req));
+    };
+
+    match verify_access_token(credentials.token()) {
+        Ok(user) if is_access_token_revoked(&user) => Err((AuthError::TokenRevoked.into_bearer_error(), req)),
         Ok(user) => {
+            // Every request made on someone else's behalf is traceable to the actor
+            if let Some(actor) = &user.actor {
+                println!(
+                    "[auth] request {} {} {}: {} acting as user:{}",
+                    current_request_id().as_deref().unwrap_or("-"),
+                    req.method(),
+                    req.path(),
+                    actor.label(),
+                    user.user_id
+                );
+            }
             req.extensions_mut().insert::<AuthenticatedUser>(user);
             Ok(req)
         }
-        Err(_e) => Err((actix_web::error::ErrorUnauthorized("invalid or expired token"), req)),
+        Err(e) => Err((e.into_bearer_error(), req)),
     }
 }
+
+async fn api_key_user(req: &ServiceRequest, key: &str) -> AuthResult<AuthenticatedUser> {
+    let (Some(keys), Some(users)) = (
+        req.app_data::<web::Data<dyn ApiKeyRepository>>().cloned(),
+        req.app_data::<web::Data<dyn UserRepository>>().cloned(),
+    ) else {
+        return Err(AuthError::Internal("api key repositories are not registered".into()));
+    };
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    authenticate_api_key(keys.get_ref(), users.get_ref(), key, now).await
+}
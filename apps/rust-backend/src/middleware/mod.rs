@@ -1 +1,4 @@
 pub mod auth;
+pub mod rbac;
+pub mod request_id;
+pub mod rate_limit;
@@ -0,0 +1,239 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpMessage};
+use serde_json::Value;
+
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::AuthError;
+use crate::auth::refresh::verify_refresh_token;
+use crate::config::get_config;
+use crate::rate_limit::{BucketLimit, Decision, RateLimitStore};
+
+/// Where the account a request acts on is named.
+#[derive(Debug, Clone, Copy)]
+pub enum AccountKey {
+    /// The first of these string fields present in the JSON body
+    JsonField(&'static [&'static str]),
+    /// The subject of the refresh token cookie
+    RefreshCookie,
+    /// The user of the bearer token; only set behind the bearer middleware
+    Bearer,
+}
+
+/// Token-bucket limits per client IP and, optionally, per account.
+///
+/// Works on a handler (`#[post("/login", wrap = "RateLimit::login()")]`) or a
+/// scope. Buckets are keyed by `name`, so every route wrapped with the same
+/// policy draws on the same budget. The IP is checked first, so a client that is
+/// already limited does not also drain the account's bucket. Over-limit requests
+/// get `429 Too Many Requests` with `Retry-After`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    name: &'static str,
+    ip: BucketLimit,
+    account: Option<(AccountKey, BucketLimit)>,
+}
+
+impl RateLimit {
+    /// For endpoints that check a password or an equivalent secret, or that send
+    /// mail to an address: limited per IP and per username or email.
+    pub fn login() -> Self {
+        let cfg = get_config();
+        Self {
+            name: "login",
+            ip: cfg.rate_limit_login_ip,
+            account: Some((AccountKey::JsonField(&["username", "email"]), cfg.rate_limit_login_account)),
+        }
+    }
+
+    /// For `/refresh`: limited per IP and per user of the refresh cookie.
+    pub fn refresh() -> Self {
+        let cfg = get_config();
+        Self {
+            name: "refresh",
+            ip: cfg.rate_limit_refresh_ip,
+            account: Some((AccountKey::RefreshCookie, cfg.rate_limit_refresh_account)),
+        }
+    }
+
+    /// For endpoints where a signed-in user proves themselves again with a code or
+    /// password: limited per IP and per user, with the login budgets.
+    pub fn reauthentication() -> Self {
+        let cfg = get_config();
+        Self {
+            name: "reauthentication",
+            ip: cfg.rate_limit_login_ip,
+            account: Some((AccountKey::Bearer, cfg.rate_limit_login_account)),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), policy: *self }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    policy: RateLimit,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let policy = self.policy;
+
+        Box::pin(async move {
+            let store = req.app_data::<web::Data<dyn RateLimitStore>>().cloned();
+            let Some(store) = store.filter(|_| get_config().rate_limit_enabled) else {
+                return service.call(req).await;
+            };
+
+            let ip = client_ip(&req);
+            check(store.get_ref(), &format!("{}:ip:{ip}", policy.name), policy.ip).await?;
+
+            if let Some((key, limit)) = policy.account
+                && let Some(account) = account_of(&mut req, key).await?
+            {
+                check(store.get_ref(), &format!("{}:account:{account}", policy.name), limit).await?;
+            }
+            service.call(req).await
+        })
+    }
+}
+
+async fn check(store: &dyn RateLimitStore, key: &str, limit: BucketLimit) -> Result<(), Error> {
+    let now_ms = (time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+    match store.take(key, limit, now_ms).await {
+        Ok(Decision::Allowed) => Ok(()),
+        Ok(Decision::Limited { retry_after_secs }) => {
+            println!("[auth] rate limited {key} for {retry_after_secs}s");
+            Err(AuthError::RateLimited { retry_after_secs }.into())
+        }
+        // A shared store being down should not take logins down with it
+        Err(e) => {
+            println!("[auth] rate limit store failed, letting {key} through: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// The peer address, or with `RATE_LIMIT_TRUST_FORWARDED` the client named by
+/// `Forwarded` / `X-Forwarded-For`, which only a trusted proxy may set.
+fn client_ip(req: &ServiceRequest) -> String {
+    let info = req.connection_info();
+    let ip = if get_config().rate_limit_trust_forwarded {
+        info.realip_remote_addr()
+    } else {
+        info.peer_addr()
+    };
+    ip.unwrap_or("unknown").to_string()
+}
+
+/// Reads the account identifier, lowercased. The JSON body is buffered and put
+/// back so the handler can still extract it.
+async fn account_of(req: &mut ServiceRequest, key: AccountKey) -> Result<Option<String>, Error> {
+    let account = match key {
+        AccountKey::JsonField(fields) => {
+            let body = req.extract::<web::Bytes>().await?;
+            let value = serde_json::from_slice::<Value>(&body).ok();
+            req.set_payload(Payload::from(body));
+            value.and_then(|v| {
+                fields
+                    .iter()
+                    .find_map(|f| v.get(*f).and_then(Value::as_str).map(str::to_string))
+            })
+        }
+        AccountKey::RefreshCookie => req
+            .cookie(&get_config().refresh_cookie_name)
+            .and_then(|c| verify_refresh_token(c.value()).ok())
+            .map(|claims| claims.sub),
+        AccountKey::Bearer => req.extensions().get::<AuthenticatedUser>().map(|user| user.user_id.clone()),
+    };
+    Ok(account
+        .map(|a| a.trim().to_lowercase())
+        .filter(|a| !a.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use super::*;
+
+    const LOGIN_FIELDS: AccountKey = AccountKey::JsonField(&["username", "email"]);
+
+    #[actix_web::test]
+    async fn account_is_read_without_consuming_the_body() {
+        let body = json!({ "email": "  Ada@Example.com ", "password": "secret" });
+        let mut req = TestRequest::post().set_json(&body).to_srv_request();
+
+        let account = account_of(&mut req, LOGIN_FIELDS).await.unwrap();
+        assert_eq!(account.as_deref(), Some("ada@example.com"));
+
+        // The handler still gets the whole body
+        let forwarded = req.extract::<web::Json<Value>>().await.unwrap();
+        assert_eq!(forwarded.into_inner(), body);
+    }
+
+    #[actix_web::test]
+    async fn first_present_field_wins_and_junk_names_no_account() {
+        let mut req = TestRequest::post()
+            .set_json(json!({ "username": "ada", "email": "other@example.com" }))
+            .to_srv_request();
+        assert_eq!(account_of(&mut req, LOGIN_FIELDS).await.unwrap().as_deref(), Some("ada"));
+
+        for body in ["not json", r#"{"username": "   "}"#, r#"{"username": 7}"#] {
+            let mut req = TestRequest::post().set_payload(body).to_srv_request();
+            assert_eq!(account_of(&mut req, LOGIN_FIELDS).await.unwrap(), None);
+            let forwarded = req.extract::<web::Bytes>().await.unwrap();
+            assert_eq!(forwarded, body.as_bytes());
+        }
+    }
+
+    #[actix_web::test]
+    async fn bearer_account_is_the_authenticated_user() {
+        let mut req = TestRequest::post().to_srv_request();
+        assert_eq!(account_of(&mut req, AccountKey::Bearer).await.unwrap(), None);
+
+        req.extensions_mut().insert(AuthenticatedUser {
+            user_id: "User-1".into(),
+            client_id: None,
+            roles: Vec::new(),
+            scope: Vec::new(),
+            jti: "token".into(),
+            exp: 0,
+            session_id: None,
+            actor: None,
+            api_key_id: None,
+        });
+        assert_eq!(account_of(&mut req, AccountKey::Bearer).await.unwrap().as_deref(), Some("user-1"));
+    }
+}
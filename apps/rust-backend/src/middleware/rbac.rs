@@ -0,0 +1,169 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpMessage};
+
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::AuthError;
+
+/// Requires the authenticated user to hold `role`. API keys never pass: they are
+/// authorized by their scopes, with [`RequirePermission`]. Neither do tokens from
+/// token exchange, which carry their subject's roles, so impersonating an admin
+/// does not make the actor one.
+///
+/// Works on a `web::scope`, a `web::resource` or a single handler
+/// (`#[get("/x", wrap = "RequireRole(\"admin\")")]`). It reads the
+/// `AuthenticatedUser` inserted by the bearer middleware, so it must sit inside it:
+/// actix runs the last `.wrap()` first, so wrap the guard before the bearer.
+#[derive(Debug, Clone, Copy)]
+pub struct RequireRole(pub &'static str);
+
+/// Requires the authenticated user to hold at least one of the listed roles.
+#[derive(Debug, Clone, Copy)]
+pub struct RequireAnyRole<const N: usize>(pub [&'static str; N]);
+
+/// Requires the token scope to grant `permission`, honouring wildcards such as
+/// `billing:*`. Placement rules are the same as for [`RequireRole`].
+#[derive(Debug, Clone, Copy)]
+pub struct RequirePermission(pub &'static str);
+
+/// Requires a token a user holds for themselves, turning away machine principals,
+/// tokens from token exchange and API keys. For account endpoints such as sessions,
+/// MFA and API keys, which an impersonating agent or a leaked key must not change.
+#[derive(Debug, Clone, Copy)]
+pub struct RequireUser;
+
+enum Requirement {
+    AnyRole(Vec<String>),
+    Permission(String),
+    User,
+}
+
+impl Requirement {
+    fn is_met_by(&self, user: &AuthenticatedUser) -> bool {
+        match self {
+            Requirement::AnyRole(roles) => {
+                user.api_key_id.is_none() && !user.is_delegated() && user.has_any_role(roles)
+            }
+            Requirement::Permission(permission) => user.has_permission(permission),
+            Requirement::User => !user.is_machine() && !user.is_delegated() && user.api_key_id.is_none(),
+        }
+    }
+
+    fn forbidden(&self) -> AuthError {
+        match self {
+            Requirement::AnyRole(roles) => {
+                AuthError::Forbidden(format!("requires one of roles: {}", roles.join(", ")))
+            }
+            Requirement::Permission(permission) => {
+                AuthError::Forbidden(format!("requires permission: {permission}"))
+            }
+            Requirement::User => AuthError::Forbidden("requires the user's own token".into()),
+        }
+    }
+}
+
+macro_rules! guard_transform {
+    ($guard:ty, $(const $n:ident: usize,)? |$this:ident| $requirement:expr) => {
+        impl<S, B, $(const $n: usize)?> Transform<S, ServiceRequest> for $guard
+        where
+            S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+            B: 'static,
+        {
+            type Response = ServiceResponse<B>;
+            type Error = Error;
+            type Transform = AuthzMiddleware<S>;
+            type InitError = ();
+            type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+            fn new_transform(&self, service: S) -> Self::Future {
+                let $this = self;
+                ready(Ok(AuthzMiddleware {
+                    service: Rc::new(service),
+                    requirement: Rc::new($requirement),
+                }))
+            }
+        }
+    };
+}
+
+guard_transform!(RequireRole, |g| Requirement::AnyRole(vec![g.0.to_string()]));
+guard_transform!(RequireAnyRole<N>, const N: usize, |g| {
+    Requirement::AnyRole(g.0.iter().map(|r| r.to_string()).collect())
+});
+guard_transform!(RequirePermission, |g| Requirement::Permission(g.0.to_string()));
+guard_transform!(RequireUser, |_g| Requirement::User);
+
+pub struct AuthzMiddleware<S> {
+    service: Rc<S>,
+    requirement: Rc<Requirement>,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for AuthzMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let allowed = req
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .map(|user| self.requirement.is_met_by(user));
+
+        let denied = match allowed {
+            Some(true) => {
+                return Box::pin(self.service.call(req));
+            }
+            // No user means the bearer middleware never ran: that is an authentication failure
+            None => AuthError::MissingCredentials,
+            Some(false) => self.requirement.forbidden(),
+        };
+
+        Box::pin(ready(Err(denied.into_bearer_error())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::claims::Actor;
+
+    fn admin() -> AuthenticatedUser {
+        AuthenticatedUser {
+            user_id: "admin".into(),
+            client_id: None,
+            roles: vec!["admin".into()],
+            scope: Vec::new(),
+            jti: "token".into(),
+            exp: 0,
+            session_id: None,
+            actor: None,
+            api_key_id: None,
+        }
+    }
+
+    #[test]
+    fn roles_only_count_on_the_users_own_token() {
+        let requirement = Requirement::AnyRole(vec!["admin".into()]);
+        assert!(requirement.is_met_by(&admin()));
+
+        let impersonated = AuthenticatedUser {
+            actor: Some(Actor { sub: Some("agent".into()), client_id: None, act: None }),
+            ..admin()
+        };
+        assert!(!requirement.is_met_by(&impersonated));
+
+        let api_key = AuthenticatedUser { api_key_id: Some("key".into()), ..admin() };
+        assert!(!requirement.is_met_by(&api_key));
+    }
+}
@@ -0,0 +1,94 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::error::InternalError;
+use actix_web::Error;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request currently being handled, if inside [`RequestId`].
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Assigns every request an id (reusing a sane incoming `X-Request-Id`), echoes it
+/// in the response and makes it available to error envelopes via
+/// [`current_request_id`]. Register it as the outermost `.wrap()` so that errors
+/// raised by inner middleware are rendered while the id is still in scope.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty() && v.len() <= 128 && v.chars().all(|c| c.is_ascii_graphic()))
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        let service = Rc::clone(&self.service);
+
+        Box::pin(REQUEST_ID.scope(id.clone(), async move {
+            let header = HeaderValue::from_str(&id).ok();
+            match service.call(req).await {
+                Ok(mut res) => {
+                    if let Some(value) = header {
+                        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                    }
+                    Ok(res)
+                }
+                // Render errors here rather than in the dispatcher, while the id is in scope.
+                // The request itself can't be kept around for this: holding a clone of it
+                // breaks routing further in.
+                Err(e) => {
+                    let mut rendered = e.error_response();
+                    if let Some(value) = header {
+                        rendered.headers_mut().insert(REQUEST_ID_HEADER, value);
+                    }
+                    Err(InternalError::from_response(e, rendered).into())
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,413 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use actix_web::web;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+
+use crate::auth::error::{AuthError, AuthResult};
+
+/// Largest response accepted from a provider; discovery documents and JWKS are small
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct OutboundRequest {
+    pub method: &'static str,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboundResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// Transport for talking to identity providers. The OIDC code only needs GET and
+/// form POST with JSON responses, so this stays small enough to swap for another
+/// client or a test double.
+#[async_trait]
+pub trait HttpClient: Send + Sync {
+    async fn send(&self, request: OutboundRequest) -> AuthResult<OutboundResponse>;
+}
+
+/// GETs `url` and parses the response as JSON, failing on non-2xx statuses.
+pub async fn get_json(client: &dyn HttpClient, url: &Url) -> AuthResult<Value> {
+    let request = OutboundRequest {
+        method: "GET",
+        url: url.clone(),
+        headers: vec![("Accept".into(), "application/json".into())],
+        body: Vec::new(),
+    };
+    json_body(url, client.send(request).await?)
+}
+
+/// POSTs `form` urlencoded, optionally with HTTP Basic client authentication.
+pub async fn post_form(
+    client: &dyn HttpClient,
+    url: &Url,
+    form: &[(&str, &str)],
+    basic_auth: Option<(&str, &str)>,
+) -> AuthResult<Value> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form)
+        .finish();
+    let mut headers = vec![
+        ("Accept".into(), "application/json".into()),
+        ("Content-Type".into(), "application/x-www-form-urlencoded".into()),
+    ];
+    if let Some((user, password)) = basic_auth {
+        // RFC 6749 section 2.3.1: both parts are form-urlencoded before base64
+        let encode = |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+        let credentials = STANDARD.encode(format!("{}:{}", encode(user), encode(password)));
+        headers.push(("Authorization".into(), format!("Basic {credentials}")));
+    }
+
+    let request = OutboundRequest { method: "POST", url: url.clone(), headers, body: body.into_bytes() };
+    json_body(url, client.send(request).await?)
+}
+
+fn json_body(url: &Url, response: OutboundResponse) -> AuthResult<Value> {
+    if !(200..300).contains(&response.status) {
+        let detail = String::from_utf8_lossy(&response.body);
+        return Err(AuthError::OidcProviderError(format!(
+            "{url} returned {}: {}",
+            response.status,
+            detail.chars().take(200).collect::<String>()
+        )));
+    }
+    serde_json::from_slice(&response.body)
+        .map_err(|e| AuthError::OidcProviderError(format!("{url} returned invalid JSON: {e}")))
+}
+
+/// Client that runs the system `curl`, which brings TLS and the platform's CA
+/// store for `https://` providers. This is the default transport.
+///
+/// Options, including client credentials, reach curl as a config file on stdin so
+/// they never show up in the process list. `~/.curlrc` is ignored and redirects
+/// are not followed.
+#[derive(Debug, Clone)]
+pub struct CurlHttpClient {
+    program: String,
+    timeout: Duration,
+}
+
+impl CurlHttpClient {
+    pub fn new(program: &str, timeout: Duration) -> Self {
+        Self { program: program.to_string(), timeout }
+    }
+
+    fn config(&self, request: &OutboundRequest) -> AuthResult<String> {
+        let mut config = format!(
+            "silent\nshow-error\nproto = \"=http,https\"\nmax-time = {}\nmax-filesize = {MAX_RESPONSE_BYTES}\n\
+             write-out = \"\\n%{{http_code}}\"\nrequest = {}\nurl = {}\n",
+            self.timeout.as_secs().max(1),
+            curl_quote(request.method),
+            curl_quote(request.url.as_str())
+        );
+        for (name, value) in &request.headers {
+            config.push_str(&format!("header = {}\n", curl_quote(&format!("{name}: {value}"))));
+        }
+        if !request.body.is_empty() {
+            let body = std::str::from_utf8(&request.body)
+                .map_err(|_| AuthError::OidcProviderError(format!("{}: request body is not UTF-8", request.url)))?;
+            // data-raw, unlike data, does not read a file when the body starts with '@'
+            config.push_str(&format!("data-raw = {}\n", curl_quote(body)));
+        }
+        Ok(config)
+    }
+}
+
+#[async_trait]
+impl HttpClient for CurlHttpClient {
+    async fn send(&self, request: OutboundRequest) -> AuthResult<OutboundResponse> {
+        let url = request.url.clone();
+        let config = self.config(&request)?;
+        let program = self.program.clone();
+        let output = web::block(move || run_curl(&program, &config))
+            .await
+            .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))?
+            .map_err(|e| AuthError::OidcProviderError(format!("{url}: running {}: {e}", self.program)))?;
+
+        if !output.status.success() {
+            let detail = String::from_utf8_lossy(&output.stderr);
+            return Err(AuthError::OidcProviderError(format!("{url}: {}", detail.trim())));
+        }
+        // write-out appends "\n<status>" after the body
+        let raw = output.stdout;
+        let split = raw.iter().rposition(|b| *b == b'\n');
+        let status = split
+            .and_then(|i| std::str::from_utf8(&raw[i + 1..]).ok())
+            .and_then(|code| code.trim().parse().ok())
+            .filter(|code| *code != 0)
+            .ok_or_else(|| AuthError::OidcProviderError(format!("{url}: malformed HTTP response")))?;
+        let mut body = raw;
+        body.truncate(split.unwrap_or_default());
+        if body.len() as u64 > MAX_RESPONSE_BYTES {
+            return Err(AuthError::OidcProviderError(format!("{url}: response too large")));
+        }
+        Ok(OutboundResponse { status, body })
+    }
+}
+
+fn run_curl(program: &str, config: &str) -> std::io::Result<std::process::Output> {
+    let mut child = Command::new(program)
+        .args(["-q", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Dropping stdin after the write closes it, so curl starts the request
+    child.stdin.take().expect("piped stdin").write_all(config.as_bytes())?;
+    child.wait_with_output()
+}
+
+/// Double-quoted string for a curl config file.
+fn curl_quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Minimal HTTP/1.1 client over plain TCP.
+///
+/// There is no TLS here, so it only reaches `http://` providers. Meant for a
+/// local mock IdP (`OIDC_HTTP_CLIENT=plain`); hosted providers go through
+/// [`CurlHttpClient`].
+#[derive(Debug, Clone)]
+pub struct PlainHttpClient {
+    timeout: Duration,
+}
+
+impl PlainHttpClient {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    async fn exchange(&self, request: &OutboundRequest) -> AuthResult<Vec<u8>> {
+        let url = &request.url;
+        let host = url
+            .host_str()
+            .ok_or_else(|| AuthError::OidcProviderError(format!("{url} has no host")))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let mut target = url.path().to_string();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let host_header = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+
+        let mut head = format!(
+            "{} {target} HTTP/1.1\r\nHost: {host_header}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            request.method,
+            request.body.len()
+        );
+        for (name, value) in &request.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let io_error = |e: std::io::Error| AuthError::OidcProviderError(format!("{url}: {e}"));
+        let mut stream = TcpStream::connect((host, port)).await.map_err(io_error)?;
+        stream.write_all(head.as_bytes()).await.map_err(io_error)?;
+        stream.write_all(&request.body).await.map_err(io_error)?;
+
+        let mut raw = Vec::new();
+        stream
+            .take(MAX_RESPONSE_BYTES + 1)
+            .read_to_end(&mut raw)
+            .await
+            .map_err(io_error)?;
+        if raw.len() as u64 > MAX_RESPONSE_BYTES {
+            return Err(AuthError::OidcProviderError(format!("{url}: response too large")));
+        }
+        Ok(raw)
+    }
+}
+
+#[async_trait]
+impl HttpClient for PlainHttpClient {
+    async fn send(&self, request: OutboundRequest) -> AuthResult<OutboundResponse> {
+        if request.url.scheme() != "http" {
+            return Err(AuthError::OidcProviderError(format!(
+                "{}: PlainHttpClient has no TLS support; use OIDC_HTTP_CLIENT=curl",
+                request.url
+            )));
+        }
+
+        let raw = tokio::time::timeout(self.timeout, self.exchange(&request))
+            .await
+            .map_err(|_| AuthError::OidcProviderError(format!("{}: timed out", request.url)))??;
+        parse_response(&raw)
+            .ok_or_else(|| AuthError::OidcProviderError(format!("{}: malformed HTTP response", request.url)))
+    }
+}
+
+fn parse_response(raw: &[u8]) -> Option<OutboundResponse> {
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&raw[..split]).ok()?;
+    let body = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+
+    let mut chunked = false;
+    let mut content_length = None;
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse::<usize>().ok();
+        }
+    }
+
+    let body = match (chunked, content_length) {
+        (true, _) => dechunk(body)?,
+        (false, Some(len)) => body.get(..len)?.to_vec(),
+        (false, None) => body.to_vec(),
+    };
+    Some(OutboundResponse { status, body })
+}
+
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size_line = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(out);
+        }
+        out.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Answers one connection with `response` and hands back the raw request.
+    async fn serve_once(response: &'static str) -> (Url, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/token?x=1", listener.local_addr().unwrap())).unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // Read the head, then as much body as Content-Length announces
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    const TOKEN_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 20\r\n\r\n{\"id_token\":\"a.b.c\"}";
+
+    #[actix_web::test]
+    async fn curl_client_posts_forms_with_basic_auth() {
+        let (url, server) = serve_once(TOKEN_RESPONSE).await;
+        let client = CurlHttpClient::new("curl", Duration::from_secs(5));
+
+        let body = post_form(&client, &url, &[("code", "a b&c"), ("grant_type", "authorization_code")], Some(("id", "p@ss")))
+            .await
+            .unwrap();
+        assert_eq!(body["id_token"], "a.b.c");
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /token?x=1 HTTP/1.1\r\n"));
+        assert!(request.contains(&format!("Authorization: Basic {}\r\n", STANDARD.encode("id:p%40ss"))));
+        assert!(request.ends_with("\r\n\r\ncode=a+b%26c&grant_type=authorization_code"));
+    }
+
+    #[actix_web::test]
+    async fn curl_client_reports_error_statuses() {
+        let (url, _server) = serve_once("HTTP/1.1 400 Bad Request\r\nContent-Length: 15\r\n\r\ninvalid_request").await;
+        let client = CurlHttpClient::new("curl", Duration::from_secs(5));
+
+        let result = get_json(&client, &url).await;
+        assert!(matches!(result, Err(AuthError::OidcProviderError(e)) if e.contains("returned 400: invalid_request")));
+    }
+
+    #[test]
+    fn curl_config_quotes_values() {
+        let client = CurlHttpClient::new("curl", Duration::from_secs(5));
+        let request = OutboundRequest {
+            method: "POST",
+            url: Url::parse("https://idp.test/token").unwrap(),
+            headers: vec![("X-Test".into(), "say \"hi\"\\now".into())],
+            body: b"@secret".to_vec(),
+        };
+        let config = client.config(&request).unwrap();
+        assert!(config.contains("url = \"https://idp.test/token\"\n"));
+        assert!(config.contains("header = \"X-Test: say \\\"hi\\\"\\\\now\"\n"));
+        assert!(config.contains("data-raw = \"@secret\"\n"));
+    }
+
+    #[actix_web::test]
+    async fn plain_client_reads_chunked_responses() {
+        let (url, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n{\"a\":\r\n2\r\n1}\r\n0\r\n\r\n",
+        )
+        .await;
+        let client = PlainHttpClient::new(Duration::from_secs(5));
+
+        let body = get_json(&client, &url).await.unwrap();
+        assert_eq!(body["a"], 1);
+        assert!(server.await.unwrap().starts_with("GET /token?x=1 HTTP/1.1\r\n"));
+    }
+
+    #[actix_web::test]
+    async fn plain_client_refuses_https() {
+        let client = PlainHttpClient::new(Duration::from_secs(5));
+        let result = get_json(&client, &Url::parse("https://accounts.google.com/").unwrap()).await;
+        assert!(matches!(result, Err(AuthError::OidcProviderError(e)) if e.contains("no TLS support")));
+    }
+}
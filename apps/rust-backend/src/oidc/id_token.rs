@@ -0,0 +1,332 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::Verifier;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::auth::error::{AuthError, AuthResult};
+
+/// One key of a provider's JWKS. Only the members needed for the supported
+/// algorithms are kept.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub alg: Option<String>,
+    #[serde(default, rename = "use")]
+    pub key_use: Option<String>,
+    /// RSA modulus and exponent
+    #[serde(default)]
+    pub n: Option<String>,
+    #[serde(default)]
+    pub e: Option<String>,
+    /// OKP curve and public key
+    #[serde(default)]
+    pub crv: Option<String>,
+    #[serde(default)]
+    pub x: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenHeader {
+    pub alg: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+}
+
+/// The verified identity asserted by an ID token.
+#[derive(Debug, Clone)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+/// What the token must match, per OpenID Connect Core section 3.1.3.7.
+#[derive(Debug, Clone)]
+pub struct Expectations<'a> {
+    pub issuer: &'a str,
+    pub client_id: &'a str,
+    pub nonce: &'a str,
+    pub leeway_secs: i64,
+}
+
+/// Reads the JOSE header without verifying anything, to pick the signing key.
+pub fn decode_header(token: &str) -> AuthResult<IdTokenHeader> {
+    let header = token.split('.').next().unwrap_or_default();
+    let bytes = b64(header)?;
+    serde_json::from_slice(&bytes).map_err(|e| invalid(format!("header: {e}")))
+}
+
+/// Verifies the signature of `token` with the matching key in `keys` and validates
+/// its claims as of `now` (unix seconds).
+pub fn verify(token: &str, keys: &[Jwk], expected: &Expectations<'_>, now: i64) -> AuthResult<IdTokenClaims> {
+    let mut parts = token.split('.');
+    let (Some(header_b64), Some(payload_b64), Some(signature_b64), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("not a compact JWS"));
+    };
+
+    let header = decode_header(token)?;
+    let signature = b64(signature_b64)?;
+    let signing_input = format!("{header_b64}.{payload_b64}");
+
+    let candidates = keys.iter().filter(|k| {
+        header.kid.is_none() || k.kid.is_none() || k.kid == header.kid
+    });
+    let mut verified = false;
+    for key in candidates {
+        if key.key_use.as_deref().is_some_and(|u| u != "sig") {
+            continue;
+        }
+        if key.alg.as_deref().is_some_and(|alg| alg != header.alg) {
+            continue;
+        }
+        if verify_signature(&header.alg, key, signing_input.as_bytes(), &signature)? {
+            verified = true;
+            break;
+        }
+    }
+    if !verified {
+        return Err(invalid("signature verification failed"));
+    }
+
+    let payload: Value = serde_json::from_slice(&b64(payload_b64)?).map_err(|e| invalid(format!("payload: {e}")))?;
+    validate_claims(&payload, expected, now)
+}
+
+fn verify_signature(alg: &str, key: &Jwk, message: &[u8], signature: &[u8]) -> AuthResult<bool> {
+    match (alg, key.kty.as_str()) {
+        ("RS256", "RSA") => {
+            let n = b64(key.n.as_deref().ok_or_else(|| invalid("RSA key without n"))?)?;
+            let e = b64(key.e.as_deref().ok_or_else(|| invalid("RSA key without e"))?)?;
+            let public = RsaPublicKey::new(BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e))
+                .map_err(|e| invalid(format!("RSA key: {e}")))?;
+            let digest = Sha256::digest(message);
+            Ok(public.verify(Pkcs1v15Sign::new::<Sha256>(), &digest, signature).is_ok())
+        }
+        ("EdDSA", "OKP") if key.crv.as_deref() == Some("Ed25519") => {
+            let x = b64(key.x.as_deref().ok_or_else(|| invalid("OKP key without x"))?)?;
+            let public = ed25519_dalek::PublicKey::from_bytes(&x).map_err(|e| invalid(format!("Ed25519 key: {e}")))?;
+            let Ok(signature) = ed25519_dalek::Signature::from_bytes(signature) else {
+                return Ok(false);
+            };
+            Ok(public.verify(message, &signature).is_ok())
+        }
+        // Keys of another type may sit in the same set; "none" and HMAC are never accepted
+        ("RS256" | "EdDSA", _) => Ok(false),
+        (alg, _) => Err(invalid(format!("unsupported alg {alg}"))),
+    }
+}
+
+fn validate_claims(payload: &Value, expected: &Expectations<'_>, now: i64) -> AuthResult<IdTokenClaims> {
+    let str_claim = |name: &str| payload.get(name).and_then(Value::as_str).map(str::to_string);
+    let int_claim = |name: &str| payload.get(name).and_then(Value::as_i64);
+
+    let iss = str_claim("iss").ok_or_else(|| invalid("missing iss"))?;
+    if iss != expected.issuer {
+        return Err(invalid("issuer mismatch"));
+    }
+
+    let audiences: Vec<&str> = match payload.get("aud") {
+        Some(Value::String(aud)) => vec![aud.as_str()],
+        Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).collect(),
+        _ => return Err(invalid("missing aud")),
+    };
+    if !audiences.contains(&expected.client_id) {
+        return Err(invalid("audience mismatch"));
+    }
+    // With several audiences the authorized party must be us
+    if audiences.len() > 1 && str_claim("azp").as_deref() != Some(expected.client_id) {
+        return Err(invalid("azp mismatch"));
+    }
+
+    let exp = int_claim("exp").ok_or_else(|| invalid("missing exp"))?;
+    if now > exp + expected.leeway_secs {
+        return Err(invalid("expired"));
+    }
+    let iat = int_claim("iat").ok_or_else(|| invalid("missing iat"))?;
+    if iat > now + expected.leeway_secs {
+        return Err(invalid("issued in the future"));
+    }
+
+    if str_claim("nonce").as_deref() != Some(expected.nonce) {
+        return Err(invalid("nonce mismatch"));
+    }
+
+    let sub = str_claim("sub").filter(|s| !s.is_empty()).ok_or_else(|| invalid("missing sub"))?;
+
+    // Some providers send email_verified as the string "true"
+    let email_verified = match payload.get("email_verified") {
+        Some(Value::Bool(verified)) => *verified,
+        Some(Value::String(verified)) => verified == "true",
+        _ => false,
+    };
+
+    Ok(IdTokenClaims { sub, email: str_claim("email"), email_verified })
+}
+
+fn b64(part: &str) -> AuthResult<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(part.trim_end_matches('='))
+        .map_err(|_| invalid("bad base64url"))
+}
+
+fn invalid(reason: impl Into<String>) -> AuthError {
+    AuthError::IdTokenInvalid(reason.into())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+    use serde_json::json;
+
+    use super::*;
+
+    pub(crate) const ISSUER: &str = "http://idp.test";
+    pub(crate) const CLIENT_ID: &str = "rust-backend";
+    pub(crate) const NONCE: &str = "n-0S6_WzA2Mj";
+    pub(crate) const NOW: i64 = 1_700_000_000;
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    /// The public half of the test signing key `seed` as an OKP JWK.
+    pub(crate) fn jwk(seed: u8, kid: &str) -> Value {
+        json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "use": "sig",
+            "alg": "EdDSA",
+            "kid": kid,
+            "x": URL_SAFE_NO_PAD.encode(keypair(seed).public.as_bytes()),
+        })
+    }
+
+    /// Claims a provider would send for `NONCE`, valid at `NOW`.
+    pub(crate) fn claims() -> Value {
+        json!({
+            "iss": ISSUER,
+            "aud": CLIENT_ID,
+            "sub": "248289761001",
+            "email": "jane@example.com",
+            "email_verified": true,
+            "nonce": NONCE,
+            "iat": NOW - 10,
+            "exp": NOW + 300,
+        })
+    }
+
+    /// Compact EdDSA JWS over `claims`, signed with the test key `seed`.
+    pub(crate) fn sign(seed: u8, kid: &str, claims: &Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "EdDSA", "kid": kid }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signature = keypair(seed).sign(format!("{header}.{payload}").as_bytes());
+        format!("{header}.{payload}.{}", URL_SAFE_NO_PAD.encode(signature.to_bytes()))
+    }
+
+    fn keys() -> Vec<Jwk> {
+        vec![serde_json::from_value(jwk(1, "k1")).unwrap()]
+    }
+
+    fn expected() -> Expectations<'static> {
+        Expectations { issuer: ISSUER, client_id: CLIENT_ID, nonce: NONCE, leeway_secs: 60 }
+    }
+
+    fn reason(result: AuthResult<IdTokenClaims>) -> String {
+        match result {
+            Err(AuthError::IdTokenInvalid(reason)) => reason,
+            other => panic!("expected IdTokenInvalid, got {other:?}"),
+        }
+    }
+
+    fn with(key: &str, value: Value) -> Value {
+        let mut claims = claims();
+        claims[key] = value;
+        claims
+    }
+
+    #[test]
+    fn accepts_a_valid_token() {
+        let claims = verify(&sign(1, "k1", &claims()), &keys(), &expected(), NOW).unwrap();
+        assert_eq!(claims.sub, "248289761001");
+        assert_eq!(claims.email.as_deref(), Some("jane@example.com"));
+        assert!(claims.email_verified);
+    }
+
+    #[test]
+    fn refuses_a_bad_signature() {
+        // Signed by a key that is not in the set, under a kid that is
+        let forged = sign(2, "k1", &claims());
+        assert_eq!(reason(verify(&forged, &keys(), &expected(), NOW)), "signature verification failed");
+
+        // Payload swapped after signing
+        let token = sign(1, "k1", &claims());
+        let other = sign(1, "k1", &with("sub", json!("someone-else")));
+        let parts: Vec<&str> = token.split('.').collect();
+        let tampered = format!("{}.{}.{}", parts[0], other.split('.').nth(1).unwrap(), parts[2]);
+        assert_eq!(reason(verify(&tampered, &keys(), &expected(), NOW)), "signature verification failed");
+    }
+
+    #[test]
+    fn refuses_unsigned_tokens() {
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "none" }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(claims().to_string());
+        let token = format!("{header}.{payload}.");
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "signature verification failed");
+    }
+
+    #[test]
+    fn refuses_a_wrong_audience() {
+        let token = sign(1, "k1", &with("aud", json!("another-client")));
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "audience mismatch");
+
+        // Several audiences need azp to name us
+        let token = sign(1, "k1", &with("aud", json!([CLIENT_ID, "another-client"])));
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "azp mismatch");
+    }
+
+    #[test]
+    fn refuses_a_wrong_nonce() {
+        let token = sign(1, "k1", &with("nonce", json!("replayed")));
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "nonce mismatch");
+
+        let mut claims = claims();
+        claims.as_object_mut().unwrap().remove("nonce");
+        let token = sign(1, "k1", &claims);
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "nonce mismatch");
+    }
+
+    #[test]
+    fn refuses_a_wrong_issuer() {
+        let token = sign(1, "k1", &with("iss", json!("http://evil.test")));
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "issuer mismatch");
+    }
+
+    #[test]
+    fn refuses_expired_tokens_after_leeway() {
+        let token = sign(1, "k1", &claims());
+        let exp = NOW + 300;
+        assert!(verify(&token, &keys(), &expected(), exp + 60).is_ok());
+        assert_eq!(reason(verify(&token, &keys(), &expected(), exp + 61)), "expired");
+    }
+
+    #[test]
+    fn refuses_tokens_issued_in_the_future() {
+        let token = sign(1, "k1", &with("iat", json!(NOW + 120)));
+        assert_eq!(reason(verify(&token, &keys(), &expected(), NOW)), "issued in the future");
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use pasetors::claims::{Claims, ClaimsValidationRules};
+use pasetors::local;
+use pasetors::token::{Local, UntrustedToken};
+use pasetors::version4::V4;
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+
+pub mod http;
+pub mod id_token;
+
+use http::{get_json, post_form, HttpClient};
+use id_token::{Expectations, IdTokenClaims, JwkSet};
+
+/// How long discovery documents and key sets are reused before being fetched again
+const METADATA_CACHE_SECS: i64 = 3600;
+
+/// Implicit assertion separating flow state from other tokens sealed with the
+/// refresh key
+const FLOW_ASSERTION: &[u8] = b"oidc-flow";
+
+/// One external identity provider, from `OIDC_<NAME>_*`.
+#[derive(Clone)]
+pub struct OidcProviderConfig {
+    /// Lowercase name used in routes, e.g. `/api/auth/oidc/google/authorize`
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// Sent with HTTP Basic at the token endpoint; public clients rely on PKCE alone
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+impl fmt::Debug for OidcProviderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcProviderConfig")
+            .field("name", &self.name)
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+/// The parts of the discovery document this backend uses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Browser-bound state of an authorization in progress, sealed into a cookie
+/// between the redirect to the provider and the callback.
+#[derive(Debug, Clone)]
+pub struct FlowState {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl FlowState {
+    pub fn new(provider: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            state: random_token(),
+            nonce: random_token(),
+            // 32 random bytes give the 43 characters RFC 7636 asks for at minimum
+            code_verifier: random_token(),
+        }
+    }
+
+    /// S256 code challenge for `code_verifier` (RFC 7636 section 4.2).
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+
+    pub fn seal(&self, ttl_secs: i64) -> AuthResult<String> {
+        let cfg = get_config();
+        let ttl = time::Duration::seconds(ttl_secs)
+            .try_into()
+            .map_err(|e| AuthError::Internal(format!("oidc flow ttl: {e}")))?;
+        let mut claims = Claims::new_expires_in(&ttl)
+            .map_err(|e| AuthError::Internal(format!("claims new: {e}")))?;
+        for (name, value) in [
+            ("provider", &self.provider),
+            ("state", &self.state),
+            ("nonce", &self.nonce),
+            ("code_verifier", &self.code_verifier),
+        ] {
+            claims
+                .add_additional(name, json!(value))
+                .map_err(|e| AuthError::Internal(format!("add {name}: {e}")))?;
+        }
+        local::encrypt(&cfg.refresh_key, &claims, None, Some(FLOW_ASSERTION))
+            .map_err(|e| AuthError::CryptoError(format!("encrypt error: {e}")))
+    }
+
+    pub fn open(sealed: &str) -> AuthResult<Self> {
+        let cfg = get_config();
+        let untrusted = UntrustedToken::<Local, V4>::try_from(sealed).map_err(|_| AuthError::OidcStateInvalid)?;
+        let trusted = local::decrypt(&cfg.refresh_key, &untrusted, &ClaimsValidationRules::new(), None, Some(FLOW_ASSERTION))
+            .map_err(|_| AuthError::OidcStateInvalid)?;
+        let payload = trusted.payload_claims().ok_or(AuthError::OidcStateInvalid)?;
+        let claim = |name: &str| {
+            payload
+                .get_claim(name)
+                .and_then(|v| v.as_str().map(|s| s.to_string()))
+                .ok_or(AuthError::OidcStateInvalid)
+        };
+        Ok(Self {
+            provider: claim("provider")?,
+            state: claim("state")?,
+            nonce: claim("nonce")?,
+            code_verifier: claim("code_verifier")?,
+        })
+    }
+}
+
+struct Cached<T> {
+    value: T,
+    fetched_at: i64,
+}
+
+/// Relying party for the configured providers. Discovery documents and key sets
+/// are cached per provider; an unknown `kid` triggers one refetch of the key set
+/// so provider key rotation is picked up without a restart.
+pub struct OidcClient {
+    http: Arc<dyn HttpClient>,
+    metadata: Mutex<HashMap<String, Cached<ProviderMetadata>>>,
+    jwks: Mutex<HashMap<String, Cached<JwkSet>>>,
+}
+
+impl OidcClient {
+    pub fn new(http: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http,
+            metadata: Mutex::new(HashMap::new()),
+            jwks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config() -> Self {
+        let cfg = get_config();
+        let timeout = Duration::from_secs(cfg.oidc_http_timeout_secs);
+        let http: Arc<dyn HttpClient> = match cfg.oidc_http_client.as_str() {
+            "plain" => Arc::new(http::PlainHttpClient::new(timeout)),
+            _ => Arc::new(http::CurlHttpClient::new(&cfg.oidc_curl_path, timeout)),
+        };
+        Self::new(http)
+    }
+
+    pub fn provider(&self, name: &str) -> AuthResult<&'static OidcProviderConfig> {
+        get_config()
+            .oidc_providers
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or(AuthError::OidcProviderNotFound)
+    }
+
+    /// Callback URL registered with the provider for `provider`.
+    pub fn redirect_uri(&self, provider: &OidcProviderConfig) -> String {
+        let cfg = get_config();
+        format!(
+            "{}/api/auth/oidc/{}/callback",
+            cfg.oidc_redirect_base_url.trim_end_matches('/'),
+            provider.name
+        )
+    }
+
+    pub async fn metadata(&self, provider: &OidcProviderConfig) -> AuthResult<ProviderMetadata> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        if let Some(cached) = self.metadata.lock().expect("oidc metadata lock").get(&provider.name)
+            && now - cached.fetched_at < METADATA_CACHE_SECS
+        {
+            return Ok(cached.value.clone());
+        }
+
+        let url = parse_url(&format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer.trim_end_matches('/')
+        ))?;
+        let document = get_json(self.http.as_ref(), &url).await?;
+        let metadata: ProviderMetadata = serde_json::from_value(document)
+            .map_err(|e| AuthError::OidcProviderError(format!("{url}: {e}")))?;
+        // OpenID Connect Discovery section 4.3
+        if metadata.issuer != provider.issuer {
+            return Err(AuthError::OidcProviderError(format!(
+                "{url} names issuer {} instead of {}",
+                metadata.issuer, provider.issuer
+            )));
+        }
+
+        self.metadata
+            .lock()
+            .expect("oidc metadata lock")
+            .insert(provider.name.clone(), Cached { value: metadata.clone(), fetched_at: now });
+        Ok(metadata)
+    }
+
+    async fn jwks(&self, provider: &OidcProviderConfig, metadata: &ProviderMetadata, refresh: bool) -> AuthResult<JwkSet> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        if !refresh
+            && let Some(cached) = self.jwks.lock().expect("oidc jwks lock").get(&provider.name)
+            && now - cached.fetched_at < METADATA_CACHE_SECS
+        {
+            return Ok(cached.value.clone());
+        }
+
+        let url = parse_url(&metadata.jwks_uri)?;
+        let set: JwkSet = serde_json::from_value(get_json(self.http.as_ref(), &url).await?)
+            .map_err(|e| AuthError::OidcProviderError(format!("{url}: {e}")))?;
+
+        self.jwks
+            .lock()
+            .expect("oidc jwks lock")
+            .insert(provider.name.clone(), Cached { value: set.clone(), fetched_at: now });
+        Ok(set)
+    }
+
+    /// URL to send the browser to, carrying the flow's state, nonce and PKCE challenge.
+    pub async fn authorization_url(&self, provider: &OidcProviderConfig, flow: &FlowState) -> AuthResult<String> {
+        let metadata = self.metadata(provider).await?;
+        let mut url = parse_url(&metadata.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri(provider))
+            .append_pair("scope", &provider.scopes)
+            .append_pair("state", &flow.state)
+            .append_pair("nonce", &flow.nonce)
+            .append_pair("code_challenge", &flow.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// Redeems the authorization code and returns the validated ID token claims.
+    pub async fn exchange_code(
+        &self,
+        provider: &OidcProviderConfig,
+        code: &str,
+        flow: &FlowState,
+        now: i64,
+    ) -> AuthResult<IdTokenClaims> {
+        let metadata = self.metadata(provider).await?;
+        let token_url = parse_url(&metadata.token_endpoint)?;
+        let redirect_uri = self.redirect_uri(provider);
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("code_verifier", flow.code_verifier.as_str()),
+        ];
+        let basic_auth = match &provider.client_secret {
+            Some(secret) => Some((provider.client_id.as_str(), secret.as_str())),
+            None => {
+                form.push(("client_id", provider.client_id.as_str()));
+                None
+            }
+        };
+        let response = post_form(self.http.as_ref(), &token_url, &form, basic_auth).await?;
+        let id_token = response
+            .get("id_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| AuthError::OidcProviderError(format!("{token_url} returned no id_token")))?;
+
+        self.verify_id_token(provider, &metadata, id_token, &flow.nonce, now).await
+    }
+
+    async fn verify_id_token(
+        &self,
+        provider: &OidcProviderConfig,
+        metadata: &ProviderMetadata,
+        token: &str,
+        nonce: &str,
+        now: i64,
+    ) -> AuthResult<IdTokenClaims> {
+        let expected = Expectations {
+            issuer: &metadata.issuer,
+            client_id: &provider.client_id,
+            nonce,
+            leeway_secs: get_config().token_leeway_secs,
+        };
+
+        let header = id_token::decode_header(token)?;
+        let mut keys = self.jwks(provider, metadata, false).await?;
+        let kid_known = |set: &JwkSet| header.kid.is_none() || set.keys.iter().any(|k| k.kid == header.kid);
+        if !kid_known(&keys) {
+            keys = self.jwks(provider, metadata, true).await?;
+        }
+        id_token::verify(token, &keys.keys, &expected, now)
+    }
+}
+
+fn parse_url(raw: &str) -> AuthResult<Url> {
+    Url::parse(raw).map_err(|e| AuthError::OidcProviderError(format!("invalid URL {raw}: {e}")))
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde_json::Value;
+
+    use super::http::{OutboundRequest, OutboundResponse};
+    use super::id_token::tests::{claims, jwk, sign, CLIENT_ID, ISSUER, NONCE, NOW};
+    use super::*;
+
+    /// Stands in for a provider: serves discovery, a JWKS and a token endpoint
+    /// answering with `id_token`, and records every request.
+    struct MockIdp {
+        issuer: String,
+        jwks: Mutex<Value>,
+        id_token: Mutex<String>,
+        requests: Mutex<Vec<OutboundRequest>>,
+    }
+
+    impl MockIdp {
+        fn new(keys: Vec<Value>, id_token: String) -> Self {
+            Self {
+                issuer: ISSUER.into(),
+                jwks: Mutex::new(json!({ "keys": keys })),
+                id_token: Mutex::new(id_token),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn requests_to(&self, path: &str) -> Vec<OutboundRequest> {
+            let requests = self.requests.lock().unwrap();
+            requests.iter().filter(|r| r.url.path() == path).cloned().collect()
+        }
+    }
+
+    #[async_trait]
+    impl HttpClient for MockIdp {
+        async fn send(&self, request: OutboundRequest) -> AuthResult<OutboundResponse> {
+            let body = match request.url.path() {
+                "/.well-known/openid-configuration" => json!({
+                    "issuer": self.issuer,
+                    "authorization_endpoint": format!("{ISSUER}/authorize"),
+                    "token_endpoint": format!("{ISSUER}/token"),
+                    "jwks_uri": format!("{ISSUER}/jwks"),
+                }),
+                "/jwks" => self.jwks.lock().unwrap().clone(),
+                "/token" => json!({ "id_token": *self.id_token.lock().unwrap(), "token_type": "Bearer" }),
+                _ => return Ok(OutboundResponse { status: 404, body: b"not found".to_vec() }),
+            };
+            self.requests.lock().unwrap().push(request);
+            Ok(OutboundResponse { status: 200, body: body.to_string().into_bytes() })
+        }
+    }
+
+    fn provider() -> OidcProviderConfig {
+        OidcProviderConfig {
+            name: "mock".into(),
+            issuer: ISSUER.into(),
+            client_id: CLIENT_ID.into(),
+            client_secret: Some("s3cret".into()),
+            scopes: "openid email".into(),
+        }
+    }
+
+    fn flow(nonce: &str) -> FlowState {
+        FlowState {
+            provider: "mock".into(),
+            state: "state-1".into(),
+            nonce: nonce.into(),
+            code_verifier: "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".into(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn authorization_url_carries_state_nonce_and_pkce() {
+        let client = OidcClient::new(Arc::new(MockIdp::new(vec![jwk(1, "k1")], String::new())));
+
+        let url = Url::parse(&client.authorization_url(&provider(), &flow(NONCE)).await.unwrap()).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query["client_id"], CLIENT_ID);
+        assert_eq!(query["state"], "state-1");
+        assert_eq!(query["nonce"], NONCE);
+        assert_eq!(query["code_challenge_method"], "S256");
+        // RFC 7636 Appendix B
+        assert_eq!(query["code_challenge"], "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[actix_web::test]
+    async fn exchanges_the_code_for_verified_claims() {
+        let idp = Arc::new(MockIdp::new(vec![jwk(1, "k1")], sign(1, "k1", &claims())));
+        let client = OidcClient::new(idp.clone());
+
+        let claims = client.exchange_code(&provider(), "code-1", &flow(NONCE), NOW).await.unwrap();
+        assert_eq!(claims.sub, "248289761001");
+        assert!(claims.email_verified);
+
+        let token_requests = idp.requests_to("/token");
+        let [request] = token_requests.as_slice() else { panic!("expected one token request") };
+        assert_eq!(request.method, "POST");
+        let form: HashMap<_, _> = url::form_urlencoded::parse(&request.body).into_owned().collect();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["code"], "code-1");
+        assert_eq!(form["code_verifier"], flow(NONCE).code_verifier);
+        // A confidential client authenticates with Basic, not in the form
+        assert!(!form.contains_key("client_id"));
+        assert!(request.headers.iter().any(|(name, value)| name == "Authorization" && value.starts_with("Basic ")));
+    }
+
+    #[actix_web::test]
+    async fn refuses_tokens_for_another_flow_or_signer() {
+        let idp = Arc::new(MockIdp::new(vec![jwk(1, "k1")], sign(1, "k1", &claims())));
+        let client = OidcClient::new(idp.clone());
+        let result = client.exchange_code(&provider(), "code-1", &flow("another-nonce"), NOW).await;
+        assert!(matches!(result, Err(AuthError::IdTokenInvalid(reason)) if reason == "nonce mismatch"));
+
+        *idp.id_token.lock().unwrap() = sign(2, "k1", &claims());
+        let result = client.exchange_code(&provider(), "code-2", &flow(NONCE), NOW).await;
+        assert!(matches!(result, Err(AuthError::IdTokenInvalid(_))));
+    }
+
+    #[actix_web::test]
+    async fn refetches_the_key_set_for_an_unknown_kid() {
+        let idp = Arc::new(MockIdp::new(vec![jwk(1, "k1")], sign(1, "k1", &claims())));
+        let client = OidcClient::new(idp.clone());
+        client.exchange_code(&provider(), "code-1", &flow(NONCE), NOW).await.unwrap();
+
+        // The provider rotates to k2; the cached set does not know it yet
+        *idp.jwks.lock().unwrap() = json!({ "keys": [jwk(1, "k1"), jwk(2, "k2")] });
+        *idp.id_token.lock().unwrap() = sign(2, "k2", &claims());
+        client.exchange_code(&provider(), "code-2", &flow(NONCE), NOW).await.unwrap();
+
+        assert_eq!(idp.requests_to("/jwks").len(), 2);
+        assert_eq!(idp.requests_to("/.well-known/openid-configuration").len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn refuses_discovery_for_another_issuer() {
+        let idp = MockIdp { issuer: "http://evil.test".into(), ..MockIdp::new(Vec::new(), String::new()) };
+        let client = OidcClient::new(Arc::new(idp));
+        let result = client.metadata(&provider()).await;
+        assert!(matches!(result, Err(AuthError::OidcProviderError(_))));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::auth::error::AuthResult;
+use crate::rate_limit::{BucketLimit, Decision, RateLimitStore};
+
+// How often take() sweeps out buckets that have refilled completely
+const PRUNE_INTERVAL_MS: i64 = 60_000;
+
+/// Process-local store; buckets are lost on restart and not shared between
+/// instances.
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimitStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    buckets: HashMap<String, Bucket>,
+    last_pruned_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_ms: i64,
+    /// From this point on the bucket is indistinguishable from a new one
+    full_at_ms: i64,
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(&self, key: &str, limit: BucketLimit, now_ms: i64) -> AuthResult<Decision> {
+        if limit.is_unlimited() {
+            return Ok(Decision::Allowed);
+        }
+        let burst = f64::from(limit.burst);
+        let per_ms = f64::from(limit.per_minute.max(1)) / 60_000.0;
+
+        let mut inner = self.inner.lock().expect("rate limit store lock");
+        if now_ms - inner.last_pruned_ms >= PRUNE_INTERVAL_MS {
+            inner.buckets.retain(|_, b| b.full_at_ms > now_ms);
+            inner.last_pruned_ms = now_ms;
+        }
+
+        let bucket = inner.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated_ms: now_ms,
+            full_at_ms: now_ms,
+        });
+        let elapsed = (now_ms - bucket.updated_ms).max(0) as f64;
+        let tokens = (bucket.tokens + elapsed * per_ms).min(burst);
+        bucket.updated_ms = now_ms;
+
+        if tokens < 1.0 {
+            bucket.tokens = tokens;
+            let retry_after_secs = ((1.0 - tokens) / per_ms / 1000.0).ceil() as u64;
+            return Ok(Decision::Limited { retry_after_secs: retry_after_secs.max(1) });
+        }
+
+        bucket.tokens = tokens - 1.0;
+        bucket.full_at_ms = now_ms + ((burst - bucket.tokens) / per_ms).ceil() as i64;
+        Ok(Decision::Allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PER_SECOND: BucketLimit = BucketLimit { burst: 3, per_minute: 60 };
+
+    async fn take(store: &InMemoryRateLimitStore, limit: BucketLimit, now_ms: i64) -> Decision {
+        store.take("key", limit, now_ms).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn burst_is_spent_then_limited() {
+        let store = InMemoryRateLimitStore::new();
+        for _ in 0..3 {
+            assert_eq!(take(&store, PER_SECOND, 0).await, Decision::Allowed);
+        }
+        assert_eq!(take(&store, PER_SECOND, 0).await, Decision::Limited { retry_after_secs: 1 });
+        // Buckets are per key
+        assert_eq!(store.take("other", PER_SECOND, 0).await.unwrap(), Decision::Allowed);
+    }
+
+    #[actix_web::test]
+    async fn tokens_refill_over_time_up_to_the_burst() {
+        let store = InMemoryRateLimitStore::new();
+        for _ in 0..3 {
+            take(&store, PER_SECOND, 0).await;
+        }
+        assert_eq!(take(&store, PER_SECOND, 999).await, Decision::Limited { retry_after_secs: 1 });
+        assert_eq!(take(&store, PER_SECOND, 1_000).await, Decision::Allowed);
+        assert!(matches!(take(&store, PER_SECOND, 1_000).await, Decision::Limited { .. }));
+
+        // A long pause refills the bucket, but never beyond the burst
+        for _ in 0..3 {
+            assert_eq!(take(&store, PER_SECOND, 600_000).await, Decision::Allowed);
+        }
+        assert!(matches!(take(&store, PER_SECOND, 600_000).await, Decision::Limited { .. }));
+    }
+
+    #[actix_web::test]
+    async fn retry_after_rounds_up_to_whole_seconds() {
+        let per_minute = BucketLimit { burst: 1, per_minute: 1 };
+        let store = InMemoryRateLimitStore::new();
+        assert_eq!(take(&store, per_minute, 0).await, Decision::Allowed);
+        assert_eq!(take(&store, per_minute, 0).await, Decision::Limited { retry_after_secs: 60 });
+        // 29.5s still missing
+        assert_eq!(take(&store, per_minute, 30_500).await, Decision::Limited { retry_after_secs: 30 });
+
+        // Never below one second, however fast the refill
+        let fast = BucketLimit { burst: 1, per_minute: 6_000 };
+        assert_eq!(store.take("fast", fast, 0).await.unwrap(), Decision::Allowed);
+        assert_eq!(store.take("fast", fast, 0).await.unwrap(), Decision::Limited { retry_after_secs: 1 });
+    }
+
+    #[actix_web::test]
+    async fn zero_burst_is_unlimited() {
+        let unlimited = BucketLimit { burst: 0, per_minute: 1 };
+        let store = InMemoryRateLimitStore::new();
+        for _ in 0..100 {
+            assert_eq!(take(&store, unlimited, 0).await, Decision::Allowed);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use async_trait::async_trait;
+
+use crate::auth::error::AuthResult;
+
+pub mod memory;
+
+pub use memory::InMemoryRateLimitStore;
+
+/// Token bucket parameters: up to `burst` requests at once, refilled at
+/// `per_minute` (at least 1). A `burst` of 0 turns the limit off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl BucketLimit {
+    pub fn is_unlimited(&self) -> bool {
+        self.burst == 0
+    }
+}
+
+/// Outcome of taking a token from a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    /// The bucket is empty; the next token arrives in `retry_after_secs`
+    Limited { retry_after_secs: u64 },
+}
+
+/// Where buckets live. The in-memory store limits each process on its own; with
+/// several instances behind a load balancer, implement this over a shared store
+/// such as Redis and register that instead.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes one token from the bucket `key`, which starts out full. `now_ms` is
+    /// passed in so every instance sharing a store agrees on the clock it uses.
+    async fn take(&self, key: &str, limit: BucketLimit, now_ms: i64) -> AuthResult<Decision>;
+}
+
+/// Builds the process-local store.
+pub fn store() -> web::Data<dyn RateLimitStore> {
+    let store: Arc<dyn RateLimitStore> = Arc::new(InMemoryRateLimitStore::new());
+    web::Data::from(store)
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::auth::error::AuthResult;
+use crate::recovery::RecoveryCodeRepository;
+
+/// Process-local repository; state is lost on restart. Meant for tests and quick demos.
+#[derive(Debug, Default)]
+pub struct InMemoryRecoveryCodeRepository {
+    /// user id -> code hash -> `used_at`
+    codes: RwLock<HashMap<String, HashMap<String, Option<i64>>>>,
+}
+
+impl InMemoryRecoveryCodeRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RecoveryCodeRepository for InMemoryRecoveryCodeRepository {
+    async fn replace(&self, user_id: &str, hashes: &[String], _now: i64) -> AuthResult<()> {
+        let mut codes = self.codes.write().expect("recovery code repository lock");
+        let fresh = hashes.iter().map(|h| (h.clone(), None)).collect();
+        codes.insert(user_id.to_string(), fresh);
+        Ok(())
+    }
+
+    async fn consume(&self, user_id: &str, hash: &str, now: i64) -> AuthResult<bool> {
+        let mut codes = self.codes.write().expect("recovery code repository lock");
+        let used_at = codes.get_mut(user_id).and_then(|user_codes| user_codes.get_mut(hash));
+        match used_at {
+            Some(used_at @ None) => {
+                *used_at = Some(now);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn remaining(&self, user_id: &str) -> AuthResult<usize> {
+        let codes = self.codes.read().expect("recovery code repository lock");
+        Ok(codes
+            .get(user_id)
+            .map(|user_codes| user_codes.values().filter(|used_at| used_at.is_none()).count())
+            .unwrap_or(0))
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use async_trait::async_trait;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::auth::error::AuthResult;
+use crate::auth::totp::base32_encode;
+use crate::storage::StorageBackend;
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemoryRecoveryCodeRepository;
+pub use sqlite::SqliteRecoveryCodeRepository;
+
+/// 80 bits per code. That is enough that a plain SHA-256 digest is safe to store
+/// and compare, unlike passwords, which need Argon2.
+const CODE_BYTES: usize = 10;
+
+/// Hashed single-use recovery codes, keyed by user.
+#[async_trait]
+pub trait RecoveryCodeRepository: Send + Sync {
+    /// Replaces every code of `user_id`, used or not, with `hashes`.
+    async fn replace(&self, user_id: &str, hashes: &[String], now: i64) -> AuthResult<()>;
+
+    /// Marks the unused code with `hash` as used. Returns `false` when there is no
+    /// such code; of two concurrent attempts with one code, only one succeeds.
+    async fn consume(&self, user_id: &str, hash: &str, now: i64) -> AuthResult<bool>;
+
+    /// Number of codes of `user_id` still unused.
+    async fn remaining(&self, user_id: &str) -> AuthResult<usize>;
+}
+
+/// Builds the repository for the configured storage backend.
+pub fn repository(storage: &StorageBackend) -> web::Data<dyn RecoveryCodeRepository> {
+    let repo: Arc<dyn RecoveryCodeRepository> = match storage {
+        StorageBackend::Memory => Arc::new(InMemoryRecoveryCodeRepository::new()),
+        StorageBackend::Sqlite(pool) => Arc::new(SqliteRecoveryCodeRepository::new(pool.clone())),
+    };
+    web::Data::from(repo)
+}
+
+/// Generates `count` codes formatted for humans, e.g. `abcd-efgh-ijkl-mnop`.
+pub fn generate_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let mut bytes = [0u8; CODE_BYTES];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let encoded = base32_encode(&bytes).to_ascii_lowercase();
+            encoded
+                .as_bytes()
+                .chunks(4)
+                .map(|chunk| std::str::from_utf8(chunk).expect("base32 is ascii"))
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Digest stored for `code`. Case, dashes and spaces are ignored, so codes can be
+/// typed back however they were written down.
+pub fn hash_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Replaces the user's codes with a fresh set and returns the plaintext codes,
+/// which are shown once and never stored.
+pub async fn regenerate_codes(repo: &dyn RecoveryCodeRepository, user_id: &str, count: usize) -> AuthResult<Vec<String>> {
+    let codes = generate_codes(count);
+    let hashes: Vec<String> = codes.iter().map(|c| hash_code(c)).collect();
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    repo.replace(user_id, &hashes, now).await?;
+    Ok(codes)
+}
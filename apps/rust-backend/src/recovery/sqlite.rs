@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use sqlx::sqlite::SqlitePool;
+
+use crate::auth::error::AuthResult;
+use crate::recovery::RecoveryCodeRepository;
+use crate::storage::storage_error;
+
+/// SQLite-backed repository over the pool opened by `storage::connect_sqlite`.
+#[derive(Debug, Clone)]
+pub struct SqliteRecoveryCodeRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRecoveryCodeRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RecoveryCodeRepository for SqliteRecoveryCodeRepository {
+    async fn replace(&self, user_id: &str, hashes: &[String], now: i64) -> AuthResult<()> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        for hash in hashes {
+            sqlx::query("INSERT INTO recovery_codes (user_id, code_hash, created_at) VALUES (?1, ?2, ?3)")
+                .bind(user_id)
+                .bind(hash)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        tx.commit().await.map_err(storage_error)
+    }
+
+    async fn consume(&self, user_id: &str, hash: &str, now: i64) -> AuthResult<bool> {
+        let result = sqlx::query(
+            "UPDATE recovery_codes SET used_at = ?3 \
+             WHERE user_id = ?1 AND code_hash = ?2 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(hash)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn remaining(&self, user_id: &str) -> AuthResult<usize> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM recovery_codes WHERE user_id = ?1 AND used_at IS NULL")
+                .bind(user_id)
+                .fetch_one(&self.pool)
+                .await
+                .map_err(storage_error)?;
+        Ok(count as usize)
+    }
+}
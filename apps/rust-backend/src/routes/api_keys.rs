@@ -0,0 +1,88 @@
+use actix_web::web::ReqData;
+use actix_web::{delete, get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api_keys::{generate_key, ApiKeyRepository};
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyPayload {
+    pub name: String,
+    /// Defaults to the scope of the token creating the key
+    pub scopes: Option<Vec<String>>,
+    /// Defaults to `API_KEY_DEFAULT_TTL_DAYS`, capped at `API_KEY_MAX_TTL_DAYS`
+    pub expires_in_days: Option<i64>,
+}
+
+/// Creates a key for the caller. The key is returned only here; it is stored
+/// hashed. It can only carry scopes the caller's own token has.
+#[post("")]
+pub async fn create_api_key(
+    user: ReqData<AuthenticatedUser>,
+    keys: web::Data<dyn ApiKeyRepository>,
+    payload: web::Json<CreateApiKeyPayload>,
+) -> AuthResult<HttpResponse> {
+    let cfg = get_config();
+    let payload = payload.into_inner();
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(AuthError::InvalidRequest("name must not be empty".into()));
+    }
+
+    let scopes = payload.scopes.unwrap_or_else(|| user.scope.clone());
+    if let Some(missing) = scopes.iter().find(|s| !user.has_permission(s)) {
+        return Err(AuthError::InvalidScope(format!("scope not held by the caller: {missing}")));
+    }
+
+    let days = payload.expires_in_days.unwrap_or(cfg.api_key_default_ttl_days);
+    if !(1..=cfg.api_key_max_ttl_days).contains(&days) {
+        return Err(AuthError::InvalidRequest(format!(
+            "expires_in_days must be between 1 and {}",
+            cfg.api_key_max_ttl_days
+        )));
+    }
+    let expires_at = time::OffsetDateTime::now_utc().unix_timestamp() + days * 86_400;
+
+    let (record, key) = generate_key(&user.user_id, name, scopes, expires_at);
+    keys.create(&record).await?;
+    println!("[auth] api key {} created for user {}", record.id, user.user_id);
+
+    Ok(HttpResponse::Created().json(json!({
+        "id": record.id,
+        "key": key,
+        "name": record.name,
+        "prefix": record.prefix,
+        "scopes": record.scopes,
+        "expires_at": record.expires_at,
+    })))
+}
+
+#[get("")]
+pub async fn list_api_keys(
+    user: ReqData<AuthenticatedUser>,
+    keys: web::Data<dyn ApiKeyRepository>,
+) -> AuthResult<HttpResponse> {
+    let listed = keys.list_for_user(&user.user_id).await?;
+    Ok(HttpResponse::Ok().json(json!({ "api_keys": listed })))
+}
+
+/// Revokes one of the caller's keys. Other users' keys are reported as not found.
+#[delete("/{id}")]
+pub async fn delete_api_key(
+    user: ReqData<AuthenticatedUser>,
+    keys: web::Data<dyn ApiKeyRepository>,
+    path: web::Path<String>,
+) -> AuthResult<HttpResponse> {
+    let id = path.into_inner();
+    match keys.find(&id).await? {
+        Some(key) if key.user_id == user.user_id => {
+            keys.delete(&id).await?;
+            println!("[auth] api key {id} revoked by user {}", user.user_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        _ => Err(AuthError::ApiKeyNotFound),
+    }
+}
@@ -1,71 +1,246 @@
 use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
 use actix_web::web::ReqData;
+use actix_web_httpauth::extractors::bearer::BearerAuth;
 use serde::Deserialize;
 use serde_json::json;
 
 use crate::auth::claims::AuthenticatedUser;
-use crate::auth::refresh::{issue_refresh_token, rotate_refresh_token, verify_refresh_token};
-use crate::auth::token::issue_access_token;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::mfa::issue_mfa_challenge;
+use crate::auth::password::{check_password_policy, hash_password};
+use crate::auth::refresh::{
+    decode_refresh_token, issue_refresh_token, refresh_token_reused, revoke_refresh_family, rotate_refresh_token,
+    verify_refresh_token,
+};
+use crate::auth::token::{issue_access_token, revoke_access_token, verify_access_token};
 use crate::config::get_config;
 use crate::lib::cookies::{clear_refresh_cookie, set_refresh_cookie};
+use crate::mail::Mailer;
+use crate::middleware::rate_limit::RateLimit;
+use crate::routes::email::{send_in_background, verification_email};
+use crate::sessions::{revoke_session, ClientInfo, Session, SessionRepository};
+use crate::users::{authenticate, User, UserRepository};
 
-#[derive(Debug, Deserialize)]
+// No Debug derive: the payload carries a plaintext password
+#[derive(Deserialize)]
 pub struct LoginPayload {
-    pub user_id: String,
-    #[serde(default)]
-    pub roles: Vec<String>,
+    /// Username or email address
+    #[serde(alias = "email")]
+    pub username: String,
+    pub password: String,
 }
 
-#[post("/login")]
-pub async fn login(payload: web::Json<LoginPayload>) -> impl Responder {
+// No Debug derive: the payload carries a plaintext password
+#[derive(Deserialize)]
+pub struct RegisterPayload {
+    pub email: String,
+    /// Defaults to the email address
+    pub username: Option<String>,
+    pub password: String,
+}
+
+/// Self-service signup. The account starts with an unverified email and a
+/// verification link is mailed to it; it does not sign the user in.
+#[post("/register", wrap = "RateLimit::login()")]
+pub async fn register(
+    users: web::Data<dyn UserRepository>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<RegisterPayload>,
+) -> AuthResult<HttpResponse> {
     let cfg = get_config();
-    let access = match issue_access_token(&payload.user_id, &payload.roles) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-    let refresh = match issue_refresh_token(&payload.user_id) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    if !cfg.signup_enabled {
+        return Err(AuthError::Forbidden("signup is disabled".into()));
+    }
 
-    let expires_at = time::OffsetDateTime::now_utc() + time::Duration::minutes(cfg.access_ttl_min);
+    let payload = payload.into_inner();
+    let email = payload.email.trim().to_string();
+    if !is_plausible_email(&email) {
+        return Err(AuthError::InvalidRequest("invalid email address".into()));
+    }
+    let username = payload
+        .username
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(|| email.clone());
+    check_password_policy(&payload.password)?;
+
+    let password = payload.password;
+    let password_hash = web::block(move || hash_password(&password))
+        .await
+        .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))??;
+    let user = users
+        .create(User::new(username, email, password_hash, cfg.signup_default_roles.clone(), Vec::new()))
+        .await?;
+    println!("[auth] user {} signed up", user.id);
+
+    send_in_background(mailer, verification_email(&user)?);
+    Ok(HttpResponse::Created().json(json!({
+        "id": user.id,
+        "username": user.username,
+        "email": user.email,
+        "email_verified": user.email_verified,
+    })))
+}
+
+/// Shape check only; the verification link is what proves the address works.
+fn is_plausible_email(email: &str) -> bool {
+    !email.contains(char::is_whitespace)
+        && email
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty() && !domain.contains('@'))
+}
+
+#[post("/login", wrap = "RateLimit::login()")]
+pub async fn login(
+    req: HttpRequest,
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    payload: web::Json<LoginPayload>,
+) -> AuthResult<HttpResponse> {
+    let user = authenticate(users.get_ref(), &payload.username, &payload.password).await?;
+
+    // Only reported after the password checked out, so it reveals nothing new
+    if get_config().require_verified_email && !user.email_verified {
+        return Err(AuthError::EmailNotVerified);
+    }
+
+    // With a second factor enrolled the password only earns a challenge, exchanged
+    // for tokens at /mfa/verify together with a valid code
+    if user.totp_enabled {
+        return mfa_challenge_response(&user);
+    }
+
+    start_session(&req, sessions.get_ref(), &user).await
+}
+
+/// First-step response for accounts with a second factor: a challenge to present
+/// with a code at `/mfa/verify` instead of tokens.
+pub fn mfa_challenge_response(user: &User) -> AuthResult<HttpResponse> {
+    let (mfa_token, challenge) = issue_mfa_challenge(&user.id)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "mfa_required": true,
+        "mfa_token": mfa_token,
+        "mfa_methods": ["totp"],
+        "expires_at": challenge.exp,
+    })))
+}
+
+/// Tokens of a freshly started session.
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: i64,
+}
+
+/// Completes a login: starts a session (a new refresh token family) and returns
+/// the access token, with the refresh token in its cookie.
+pub async fn start_session(
+    req: &HttpRequest,
+    sessions: &dyn SessionRepository,
+    user: &User,
+) -> AuthResult<HttpResponse> {
+    let tokens = create_session(req, sessions, user).await?;
 
     let mut resp = HttpResponse::Ok().json(json!({
         "token_type": "Bearer",
-        "access_token": access,
-        "expires_at": expires_at.unix_timestamp(),
+        "access_token": tokens.access_token,
+        "expires_at": tokens.expires_at,
         "user": {
-            "id": payload.user_id,
-            "roles": payload.roles,
+            "id": user.id,
+            "email": user.email,
+            "roles": user.roles,
+            "permissions": user.permissions,
         }
     }));
 
-    set_refresh_cookie(&mut resp, &refresh);
-    resp
+    set_refresh_cookie(&mut resp, &tokens.refresh_token);
+    Ok(resp)
 }
 
-#[post("/refresh")]
-pub async fn refresh(req: HttpRequest) -> impl Responder {
+/// Records a new session for `user` and issues its refresh and access tokens.
+pub async fn create_session(
+    req: &HttpRequest,
+    sessions: &dyn SessionRepository,
+    user: &User,
+) -> AuthResult<SessionTokens> {
+    let cfg = get_config();
+
+    let (refresh_token, refresh_claims) = issue_refresh_token(&user.id)?;
+    let session = Session::new(
+        refresh_claims.family_id,
+        user.id.clone(),
+        refresh_claims.jti,
+        refresh_claims.exp,
+        ClientInfo::from_request(req),
+    );
+    sessions.create(&session).await?;
+
+    // Roles and permissions come from the user record, never from the request
+    let access_token = issue_access_token(&user.id, &user.roles, &user.permissions, Some(&session.id))?;
+
+    let expires_at = time::OffsetDateTime::now_utc() + time::Duration::minutes(cfg.access_ttl_min);
+
+    Ok(SessionTokens {
+        access_token,
+        refresh_token,
+        expires_at: expires_at.unix_timestamp(),
+    })
+}
+
+#[post("/refresh", wrap = "RateLimit::refresh()")]
+pub async fn refresh(
+    req: HttpRequest,
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+) -> AuthResult<HttpResponse> {
     let cfg = get_config();
     let cookie_name = cfg.refresh_cookie_name.clone();
 
-    let cookie = match req.cookie(&cookie_name) {
-        Some(c) => c,
-        None => return HttpResponse::Unauthorized().finish(),
-    };
+    let cookie = req.cookie(&cookie_name).ok_or(AuthError::MissingCredentials)?;
 
-    // verify and rotate refresh token
-    let (new_refresh, claims) = match rotate_refresh_token(cookie.value()) {
-        Ok(tuple) => tuple,
-        Err(_) => return HttpResponse::Unauthorized().finish(),
+    // Rotate first: rotation consumes the token and is where replayed tokens are caught
+    // A replayed token ends its session for good, not only the token family
+    let presented = decode_refresh_token(cookie.value())?;
+    let (new_refresh, claims) = match rotate_refresh_token(&presented) {
+        Err(AuthError::RefreshTokenReused) => {
+            revoke_session(sessions.get_ref(), &presented.family_id).await?;
+            return Err(AuthError::RefreshTokenReused);
+        }
+        rotated => rotated?,
     };
 
-    // issue fresh access token; roles are not encoded in refresh, so caller gets empty roles by default
-    let access = match issue_access_token(&claims.sub, &Vec::new()) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    // Roles and permissions are not encoded in the refresh token; the user record is
+    // authoritative, so role changes and disabled accounts take effect on the next refresh
+    let user = match users.find_by_id(&claims.sub).await? {
+        Some(user) if !user.disabled => user,
+        _ => {
+            revoke_refresh_family(&claims.family_id);
+            return Err(AuthError::RefreshTokenInvalid);
+        }
     };
 
+    // The stored session is authoritative too: revocation lists are in memory, so
+    // this is what keeps an ended session ended across restarts
+    let session = sessions.find(&claims.family_id).await?;
+    if session.is_none_or(|s| s.user_id != user.id) {
+        revoke_refresh_family(&claims.family_id);
+        return Err(AuthError::RefreshTokenInvalid);
+    }
+    // Only the session's current token may be rotated. This catches rotated tokens
+    // replayed after a restart emptied the in-memory revocation list
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let client = ClientInfo::from_request(&req);
+    if !sessions
+        .touch(&claims.family_id, &presented.jti, &claims.jti, now, claims.exp, &client)
+        .await?
+    {
+        let reused = refresh_token_reused(&presented);
+        revoke_session(sessions.get_ref(), &presented.family_id).await?;
+        return Err(reused);
+    }
+
+    let access = issue_access_token(&user.id, &user.roles, &user.permissions, Some(&claims.family_id))?;
+
     let expires_at = time::OffsetDateTime::now_utc() + time::Duration::minutes(cfg.access_ttl_min);
 
     let mut resp = HttpResponse::Ok().json(json!({
@@ -75,14 +250,36 @@ pub async fn refresh(req: HttpRequest) -> impl Responder {
     }));
 
     set_refresh_cookie(&mut resp, &new_refresh);
-    resp
+    Ok(resp)
 }
 
+/// Ends the session of the refresh cookie and revokes the bearer access token, when
+/// sent. Logout sits outside the bearer scope so that a client with an expired access
+/// token can still end its session.
 #[post("/logout")]
-pub async fn logout() -> impl Responder {
+pub async fn logout(
+    req: HttpRequest,
+    bearer: Option<BearerAuth>,
+    sessions: web::Data<dyn SessionRepository>,
+) -> AuthResult<HttpResponse> {
+    let cfg = get_config();
+
+    // Best effort: an already invalid token has nothing left to revoke
+    if let Some(cookie) = req.cookie(&cfg.refresh_cookie_name)
+        && let Ok(claims) = verify_refresh_token(cookie.value())
+    {
+        revoke_session(sessions.get_ref(), &claims.family_id).await?;
+    }
+
+    if let Some(bearer) = bearer
+        && let Ok(user) = verify_access_token(bearer.token())
+    {
+        revoke_access_token(&user);
+    }
+
     let mut resp = HttpResponse::Ok().finish();
     clear_refresh_cookie(&mut resp);
-    resp
+    Ok(resp)
 }
 
 #[get("/me")]
@@ -92,8 +289,69 @@ pub async fn me(user: ReqData<AuthenticatedUser>) -> impl Responder {
         "user": {
             "id": user.user_id,
             "roles": user.roles,
+            "permissions": user.scope,
         },
+        "client_id": user.client_id,
+        "act": user.actor,
         "exp": user.exp,
-        "jti": user.jti
+        "jti": user.jti,
+        "session_id": user.session_id,
+        "api_key_id": user.api_key_id
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::cookie::Cookie;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::sessions::InMemorySessionRepository;
+    use crate::users::InMemoryUserRepository;
+
+    #[actix_web::test]
+    async fn replaying_a_rotated_token_ends_the_session() {
+        let users: Arc<dyn UserRepository> = Arc::new(InMemoryUserRepository::new());
+        let sessions: Arc<dyn SessionRepository> = Arc::new(InMemorySessionRepository::new());
+        let user = User::new("ada".into(), "ada@example.com".into(), "hash".into(), Vec::new(), Vec::new());
+        let user = users.create(user).await.unwrap();
+        let first = create_session(&test::TestRequest::default().to_http_request(), sessions.as_ref(), &user)
+            .await
+            .unwrap()
+            .refresh_token;
+        let family_id = verify_refresh_token(&first).unwrap().family_id;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(users))
+                .app_data(web::Data::from(sessions.clone()))
+                .service(refresh),
+        )
+        .await;
+        let refresh_with = |token: &str| {
+            let cookie = Cookie::new(get_config().refresh_cookie_name.clone(), token.to_string());
+            test::TestRequest::post().uri("/refresh").cookie(cookie).to_request()
+        };
+
+        let resp = test::call_service(&app, refresh_with(&first)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let second = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == get_config().refresh_cookie_name)
+            .unwrap()
+            .value()
+            .to_string();
+
+        let resp = test::call_service(&app, refresh_with(&first)).await;
+        assert!(resp.status().is_client_error());
+        assert!(sessions.find(&family_id).await.unwrap().is_none());
+
+        // The legitimate holder's newer token went down with the family
+        let resp = test::call_service(&app, refresh_with(&second)).await;
+        assert!(resp.status().is_client_error());
+    }
+}
@@ -0,0 +1,74 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::clients::{generate_secret, hash_secret, Client, ClientRepository};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateClientPayload {
+    pub name: String,
+    /// Permissions the client may request, e.g. `["orders:read"]`
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Registers a confidential client. The secret is returned only here; it is
+/// stored hashed.
+#[post("")]
+pub async fn create_client(
+    clients: web::Data<dyn ClientRepository>,
+    payload: web::Json<CreateClientPayload>,
+) -> AuthResult<HttpResponse> {
+    let payload = payload.into_inner();
+    if payload.name.trim().is_empty() {
+        return Err(AuthError::InvalidRequest("name must not be empty".into()));
+    }
+
+    let secret = generate_secret();
+    let client = Client::new(
+        uuid::Uuid::new_v4().to_string(),
+        payload.name,
+        hash_secret(&secret),
+        payload.scopes,
+    );
+    let client = clients.create(client).await?;
+
+    Ok(HttpResponse::Created().json(json!({
+        "client_id": client.id,
+        "client_secret": secret,
+        "name": client.name,
+        "scopes": client.scopes,
+    })))
+}
+
+#[get("")]
+pub async fn list_clients(clients: web::Data<dyn ClientRepository>) -> AuthResult<HttpResponse> {
+    let listed: Vec<_> = clients
+        .list()
+        .await?
+        .into_iter()
+        .map(|c| {
+            json!({
+                "client_id": c.id,
+                "name": c.name,
+                "scopes": c.scopes,
+                "disabled": c.disabled,
+                "created_at": c.created_at,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "clients": listed })))
+}
+
+/// Removes a client. Tokens it already holds stay valid until they expire.
+#[delete("/{id}")]
+pub async fn delete_client(
+    clients: web::Data<dyn ClientRepository>,
+    path: web::Path<String>,
+) -> AuthResult<HttpResponse> {
+    if !clients.delete(&path).await? {
+        return Err(AuthError::ClientNotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,97 @@
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::email_token::{consume_email_token, issue_email_token, verify_email_token, EmailTokenPurpose};
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::events::{self, AuthEvent};
+use crate::config::get_config;
+use crate::mail::{Email, Mailer};
+use crate::middleware::rate_limit::RateLimit;
+use crate::users::{User, UserRepository};
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailPayload {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResendVerificationPayload {
+    pub email: String,
+}
+
+/// Redeems the token from a verification email.
+#[post("/verify")]
+pub async fn verify_email(
+    users: web::Data<dyn UserRepository>,
+    payload: web::Json<VerifyEmailPayload>,
+) -> AuthResult<HttpResponse> {
+    let token = verify_email_token(EmailTokenPurpose::VerifyEmail, &payload.token)?;
+    let user = match users.find_by_id(&token.sub).await? {
+        Some(user) if !user.disabled && token.matches(&user) => user,
+        _ => return Err(AuthError::EmailTokenInvalid),
+    };
+    consume_email_token(&token)?;
+
+    if !user.email_verified {
+        users.mark_email_verified(&user.id).await?;
+        events::emit(AuthEvent::EmailVerified {
+            sub: user.id.clone(),
+            email: user.email.clone(),
+        });
+    }
+    Ok(HttpResponse::Ok().json(json!({ "email": user.email, "email_verified": true })))
+}
+
+/// Sends a fresh verification link. Always accepted, and the mail goes out in the
+/// background, so the response tells nothing about which addresses have accounts.
+#[post("/verify/resend", wrap = "RateLimit::login()")]
+pub async fn resend_verification(
+    users: web::Data<dyn UserRepository>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<ResendVerificationPayload>,
+) -> AuthResult<HttpResponse> {
+    if let Some(user) = users.find_by_email(payload.email.trim()).await?
+        && !user.disabled
+        && !user.email_verified
+    {
+        send_in_background(mailer, verification_email(&user)?);
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Message with a link for `user` to verify their address.
+pub fn verification_email(user: &User) -> AuthResult<Email> {
+    let cfg = get_config();
+    let token = issue_email_token(EmailTokenPurpose::VerifyEmail, user)?;
+    let link = format!("{}/verify-email?token={token}", cfg.mail_link_base_url.trim_end_matches('/'));
+    Ok(Email {
+        to: user.email.clone(),
+        subject: "Verify your email address".into(),
+        body: format!(
+            "Hello {},\n\nplease confirm that this is your email address by opening this link:\n\n{link}\n\n\
+             The link is valid for {}. If you did not sign up, ignore this message.\n",
+            user.username,
+            validity(cfg.email_verification_ttl_secs)
+        ),
+    })
+}
+
+/// Sends `email` after the response has gone out. Failures are logged, not
+/// reported, so the response is the same whether or not a message was due.
+pub fn send_in_background(mailer: web::Data<dyn Mailer>, email: Email) {
+    actix_web::rt::spawn(async move {
+        if let Err(e) = mailer.send(&email).await {
+            println!("[auth] mail to {} failed: {e}", email.to);
+        }
+    });
+}
+
+/// Link lifetime in words, e.g. "30 minutes" or "24 hours".
+pub fn validity(ttl_secs: i64) -> String {
+    if ttl_secs >= 7200 {
+        format!("{} hours", ttl_secs / 3600)
+    } else {
+        format!("{} minutes", (ttl_secs / 60).max(1))
+    }
+}
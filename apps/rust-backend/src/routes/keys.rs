@@ -0,0 +1,60 @@
+use actix_web::http::header::{self, CacheControl, CacheDirective, EntityTag, IfNoneMatch};
+use actix_web::web::Header;
+use actix_web::{get, HttpResponse, Responder};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::config::get_config;
+
+/// Publishes the access token verifying keys as PASERK `k4.public` strings so
+/// other services can verify tokens offline. Responses are cacheable and carry
+/// an ETag derived from the key set, so polling clients mostly get 304s.
+#[get("/keys")]
+pub async fn keys(if_none_match: Option<Header<IfNoneMatch>>) -> impl Responder {
+    let cfg = get_config();
+
+    let keys: Vec<_> = cfg
+        .keyring
+        .published_keys()
+        .map(|k| {
+            json!({
+                "kid": k.kid,
+                "paserk": k.public_paserk(),
+                "status": k.status,
+                "not_before": k.not_before,
+                "not_after": k.not_after,
+            })
+        })
+        .collect();
+
+    // SHA-256 of the published set, so every build and instance derives the same tag
+    let keys = json!({ "keys": keys });
+    let tag: String = Sha256::digest(keys.to_string().as_bytes())[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let etag = EntityTag::new_strong(tag);
+
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(cfg.keys_cache_max_age_secs),
+    ]);
+
+    if let Some(Header(condition)) = if_none_match {
+        let matches = match condition {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|t| t.weak_eq(&etag)),
+        };
+        if matches {
+            return HttpResponse::NotModified()
+                .insert_header(cache_control)
+                .insert_header(header::ETag(etag))
+                .finish();
+        }
+    }
+
+    HttpResponse::Ok()
+        .insert_header(cache_control)
+        .insert_header(header::ETag(etag))
+        .json(keys)
+}
@@ -0,0 +1,92 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+
+use crate::auth::email_token::{consume_email_token, issue_email_token, verify_email_token, EmailTokenPurpose};
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+use crate::mail::{Email, Mailer};
+use crate::middleware::rate_limit::RateLimit;
+use crate::routes::auth::{mfa_challenge_response, start_session};
+use crate::routes::email::{send_in_background, validity};
+use crate::sessions::SessionRepository;
+use crate::users::{User, UserRepository};
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkPayload {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsumeMagicLinkPayload {
+    pub token: String,
+}
+
+/// Mails a one-time sign-in link to the account with this address, if there is
+/// one. Always accepted, and the mail goes out in the background, so the response
+/// tells nothing about which addresses have accounts.
+#[post("", wrap = "RateLimit::login()")]
+pub async fn request_magic_link(
+    users: web::Data<dyn UserRepository>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<MagicLinkPayload>,
+) -> AuthResult<HttpResponse> {
+    if let Some(user) = users.find_by_email(payload.email.trim()).await?
+        && !user.disabled
+    {
+        send_in_background(mailer, magic_link_email(&user)?);
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Exchanges a magic-link token for a session, answering like `/login`. Each token
+/// works once, and only while the account still has the address it was sent to.
+#[post("/consume")]
+pub async fn consume_magic_link(
+    req: HttpRequest,
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    payload: web::Json<ConsumeMagicLinkPayload>,
+) -> AuthResult<HttpResponse> {
+    let token = verify_email_token(EmailTokenPurpose::MagicLink, &payload.token)?;
+    let mut user = match users.find_by_id(&token.sub).await? {
+        Some(user) if !user.disabled && token.matches(&user) => user,
+        _ => return Err(AuthError::EmailTokenInvalid),
+    };
+    consume_email_token(&token)?;
+    // The stored generation is what keeps the link single-use across restarts; it
+    // also retires every other link sent before this one was redeemed
+    if !users.advance_magic_link_generation(&user.id, user.magic_link_generation).await? {
+        return Err(AuthError::EmailTokenInvalid);
+    }
+
+    // Receiving the link proved the address works
+    if !user.email_verified {
+        users.mark_email_verified(&user.id).await?;
+        user.email_verified = true;
+    }
+    println!("[auth] user {} signed in with a magic link", user.id);
+
+    // The link stands in for the password only; an enrolled second factor is still
+    // asked for
+    if user.totp_enabled {
+        return mfa_challenge_response(&user);
+    }
+    start_session(&req, sessions.get_ref(), &user).await
+}
+
+/// Message with a one-time link for `user` to sign in with.
+fn magic_link_email(user: &User) -> AuthResult<Email> {
+    let cfg = get_config();
+    let token = issue_email_token(EmailTokenPurpose::MagicLink, user)?;
+    let link = format!("{}/magic-link?token={token}", cfg.mail_link_base_url.trim_end_matches('/'));
+    Ok(Email {
+        to: user.email.clone(),
+        subject: "Your sign-in link".into(),
+        body: format!(
+            "Hello {},\n\nopen this link to sign in:\n\n{link}\n\nThe link is valid for {} and works \
+             once. If you did not ask to sign in, ignore this message.\n",
+            user.username,
+            validity(cfg.magic_link_ttl_secs)
+        ),
+    })
+}
@@ -0,0 +1,150 @@
+use actix_web::web::ReqData;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::mfa::{
+    consume_mfa_challenge, open_secret, record_failed_attempt, seal_secret, verify_mfa_challenge,
+};
+use crate::auth::totp;
+use crate::config::get_config;
+use crate::middleware::rate_limit::RateLimit;
+use crate::recovery::{hash_code, RecoveryCodeRepository};
+use crate::routes::auth::start_session;
+use crate::sessions::SessionRepository;
+use crate::users::{User, UserRepository};
+
+#[derive(Deserialize)]
+pub struct CodePayload {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaVerifyPayload {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+/// Starts TOTP enrollment with a fresh secret. Nothing changes at login until the
+/// secret is confirmed with a code; enrolling again replaces an unconfirmed secret.
+#[post("/enroll")]
+pub async fn enroll_totp(
+    user: ReqData<AuthenticatedUser>,
+    users: web::Data<dyn UserRepository>,
+) -> AuthResult<HttpResponse> {
+    let cfg = get_config();
+    let mut account = find_account(users.get_ref(), &user.user_id).await?;
+    if account.totp_enabled {
+        return Err(AuthError::MfaAlreadyEnabled);
+    }
+
+    let secret = totp::generate_secret();
+    account.totp_secret = Some(seal_secret(&account.id, &secret)?);
+    account.totp_last_step = 0;
+    users.update(&account).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "secret": totp::base32_encode(&secret),
+        "otpauth_uri": totp::provisioning_uri(&cfg.totp_issuer, &account.email, &secret),
+        "digits": totp::DIGITS,
+        "period": totp::STEP_SECS,
+    })))
+}
+
+/// Turns TOTP on once the user proves their authenticator produces valid codes.
+#[post("/confirm")]
+pub async fn confirm_totp(
+    user: ReqData<AuthenticatedUser>,
+    users: web::Data<dyn UserRepository>,
+    payload: web::Json<CodePayload>,
+) -> AuthResult<HttpResponse> {
+    let mut account = find_account(users.get_ref(), &user.user_id).await?;
+    if account.totp_enabled {
+        return Err(AuthError::MfaAlreadyEnabled);
+    }
+
+    account.totp_last_step = check_code(&account, &payload.code)?;
+    account.totp_enabled = true;
+    users.update(&account).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "mfa_enabled": true })))
+}
+
+/// Turns TOTP off; takes a current code so a stolen access token alone can't. A
+/// recovery code is accepted (and consumed) instead, for a lost authenticator.
+/// Rate limited per user, so the code can't be guessed.
+#[post("/disable", wrap = "RateLimit::reauthentication()")]
+pub async fn disable_totp(
+    user: ReqData<AuthenticatedUser>,
+    users: web::Data<dyn UserRepository>,
+    recovery: web::Data<dyn RecoveryCodeRepository>,
+    payload: web::Json<CodePayload>,
+) -> AuthResult<HttpResponse> {
+    let mut account = find_account(users.get_ref(), &user.user_id).await?;
+    if !account.totp_enabled {
+        return Err(AuthError::MfaNotEnrolled);
+    }
+
+    if let Err(e) = check_code(&account, &payload.code) {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        if !recovery.consume(&account.id, &hash_code(&payload.code), now).await? {
+            return Err(e);
+        }
+    }
+    account.totp_secret = None;
+    account.totp_enabled = false;
+    account.totp_last_step = 0;
+    users.update(&account).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "mfa_enabled": false })))
+}
+
+/// Second login step: exchanges the challenge from `/login` and a TOTP code for
+/// the same tokens a password-only login returns.
+#[post("/verify", wrap = "RateLimit::login()")]
+pub async fn verify_mfa(
+    req: HttpRequest,
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    payload: web::Json<MfaVerifyPayload>,
+) -> AuthResult<HttpResponse> {
+    let challenge = verify_mfa_challenge(&payload.mfa_token)?;
+
+    let account = match users.find_by_id(&challenge.sub).await? {
+        Some(account) if !account.disabled && account.totp_enabled => account,
+        _ => return Err(AuthError::MfaChallengeInvalid),
+    };
+
+    let step = match check_code(&account, &payload.code) {
+        Ok(step) => step,
+        Err(e) => {
+            record_failed_attempt(&challenge);
+            return Err(e);
+        }
+    };
+    // Recorded only if no other request got there first, so a code sent twice at
+    // once is accepted once
+    if !users.record_totp_step(&account.id, step).await? {
+        record_failed_attempt(&challenge);
+        return Err(AuthError::InvalidMfaCode);
+    }
+    consume_mfa_challenge(&challenge)?;
+
+    start_session(&req, sessions.get_ref(), &account).await
+}
+
+async fn find_account(users: &dyn UserRepository, id: &str) -> AuthResult<User> {
+    users.find_by_id(id).await?.ok_or(AuthError::UserNotFound)
+}
+
+/// Verifies `code` against the account's secret and returns the matched time step.
+pub fn check_code(account: &User, code: &str) -> AuthResult<i64> {
+    let cfg = get_config();
+    let sealed = account.totp_secret.as_deref().ok_or(AuthError::MfaNotEnrolled)?;
+    let secret = open_secret(&account.id, sealed)?;
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    totp::verify(&secret, code, now, cfg.totp_skew_steps, account.totp_last_step)
+        .ok_or(AuthError::InvalidMfaCode)
+}
@@ -1,2 +1,14 @@
 pub mod auth;
+pub mod keys;
 pub mod protected;
+pub mod sessions;
+pub mod mfa;
+pub mod recovery;
+pub mod oidc;
+pub mod token;
+pub mod clients;
+pub mod api_keys;
+pub mod email;
+pub mod password;
+pub mod magic_link;
+pub mod users;
@@ -0,0 +1,98 @@
+use actix_web::http::header::LOCATION;
+use actix_web::{get, web, HttpRequest, HttpResponse, ResponseError};
+use serde::Deserialize;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+use crate::identities::{resolve_user, IdentityRepository};
+use crate::lib::cookies::{clear_oidc_flow_cookie, set_oidc_flow_cookie, set_refresh_cookie, OIDC_FLOW_COOKIE};
+use crate::oidc::{FlowState, OidcClient};
+use crate::routes::auth::{create_session, mfa_challenge_response, start_session};
+use crate::sessions::SessionRepository;
+use crate::users::UserRepository;
+
+/// How long a user has to finish signing in at the provider
+const FLOW_TTL_SECS: i64 = 600;
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set instead of `code` when the provider refused or the user cancelled
+    pub error: Option<String>,
+}
+
+/// Starts sign-in with `provider`: redirects the browser to its authorization
+/// endpoint and remembers state, nonce and PKCE verifier in a short-lived cookie.
+#[get("/{provider}/authorize")]
+pub async fn authorize(path: web::Path<String>, oidc: web::Data<OidcClient>) -> AuthResult<HttpResponse> {
+    let provider = oidc.provider(&path)?;
+    let flow = FlowState::new(&provider.name);
+    let location = oidc.authorization_url(provider, &flow).await?;
+
+    let mut resp = HttpResponse::Found().insert_header((LOCATION, location)).finish();
+    set_oidc_flow_cookie(&mut resp, &flow.seal(FLOW_TTL_SECS)?, FLOW_TTL_SECS);
+    Ok(resp)
+}
+
+/// Redirect target registered with the provider. Redeems the code, validates the
+/// ID token and signs the matching local account in the same way `/login` does.
+#[get("/{provider}/callback")]
+pub async fn callback(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<CallbackQuery>,
+    oidc: web::Data<OidcClient>,
+    users: web::Data<dyn UserRepository>,
+    identities: web::Data<dyn IdentityRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+) -> AuthResult<HttpResponse> {
+    let cfg = get_config();
+    let provider = oidc.provider(&path)?;
+
+    // The state must come back to the browser that started the flow; this is the
+    // CSRF protection of the callback
+    let cookie = req.cookie(OIDC_FLOW_COOKIE).ok_or(AuthError::OidcStateInvalid)?;
+    let flow = FlowState::open(cookie.value())?;
+    if flow.provider != provider.name || query.state.as_deref() != Some(flow.state.as_str()) {
+        return Err(AuthError::OidcStateInvalid);
+    }
+
+    if let Some(error) = &query.error {
+        return Err(AuthError::InvalidRequest(format!("sign-in was not completed: {error}")));
+    }
+    let code = query
+        .code
+        .as_deref()
+        .ok_or_else(|| AuthError::InvalidRequest("missing code".into()))?;
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let claims = oidc
+        .exchange_code(provider, code, &flow, now)
+        .await
+        .inspect_err(|e| {
+            // Server errors are logged with the response; the ID token reason is not
+            if !e.status_code().is_server_error() {
+                println!("[auth] oidc sign-in with {} failed: {e}", provider.name);
+            }
+        })?;
+
+    let user = resolve_user(identities.get_ref(), users.get_ref(), &provider.name, &claims).await?;
+
+    // The provider stands in for the password only; an enrolled second factor is
+    // still asked for
+    let mut resp = if user.totp_enabled {
+        mfa_challenge_response(&user)?
+    } else if let Some(location) = &cfg.oidc_post_login_redirect {
+        // Browser flows land on the frontend, which gets its access token from /refresh
+        let tokens = create_session(&req, sessions.get_ref(), &user).await?;
+        let mut resp = HttpResponse::SeeOther().insert_header((LOCATION, location.as_str())).finish();
+        set_refresh_cookie(&mut resp, &tokens.refresh_token);
+        resp
+    } else {
+        start_session(&req, sessions.get_ref(), &user).await?
+    };
+
+    clear_oidc_flow_cookie(&mut resp);
+    Ok(resp)
+}
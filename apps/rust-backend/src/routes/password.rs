@@ -0,0 +1,97 @@
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::email_token::{consume_email_token, issue_email_token, verify_email_token, EmailTokenPurpose};
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::events::{self, AuthEvent};
+use crate::auth::password::{check_password_policy, hash_password};
+use crate::config::get_config;
+use crate::mail::{Email, Mailer};
+use crate::middleware::rate_limit::RateLimit;
+use crate::routes::email::{send_in_background, validity};
+use crate::sessions::{revoke_user_sessions, SessionRepository};
+use crate::users::{User, UserRepository};
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordPayload {
+    pub email: String,
+}
+
+// No Debug derive: the payload carries a plaintext password
+#[derive(Deserialize)]
+pub struct ResetPasswordPayload {
+    pub token: String,
+    pub password: String,
+}
+
+/// Mails a reset link to the account with this address, if there is one. The
+/// response is the same either way, and the mail goes out in the background, so
+/// neither tells which addresses have accounts.
+#[post("/forgot", wrap = "RateLimit::login()")]
+pub async fn forgot_password(
+    users: web::Data<dyn UserRepository>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<ForgotPasswordPayload>,
+) -> AuthResult<HttpResponse> {
+    if let Some(user) = users.find_by_email(payload.email.trim()).await?
+        && !user.disabled
+    {
+        send_in_background(mailer, password_reset_email(&user)?);
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Sets a new password with the token from a reset email and ends every session
+/// of the user, so refresh tokens issued before the reset stop working.
+#[post("/reset")]
+pub async fn reset_password(
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    payload: web::Json<ResetPasswordPayload>,
+) -> AuthResult<HttpResponse> {
+    let payload = payload.into_inner();
+    let token = verify_email_token(EmailTokenPurpose::ResetPassword, &payload.token)?;
+    let user = match users.find_by_id(&token.sub).await? {
+        Some(user) if !user.disabled && token.matches(&user) => user,
+        _ => return Err(AuthError::EmailTokenInvalid),
+    };
+    check_password_policy(&payload.password)?;
+
+    let password = payload.password;
+    let password_hash = web::block(move || hash_password(&password))
+        .await
+        .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))??;
+    consume_email_token(&token)?;
+
+    users.set_password_hash(&user.id, &password_hash).await?;
+    // Following the link proved the address works
+    if !user.email_verified {
+        users.mark_email_verified(&user.id).await?;
+    }
+
+    let revoked_sessions = revoke_user_sessions(sessions.get_ref(), &user.id, None).await?;
+    events::emit(AuthEvent::PasswordReset {
+        sub: user.id.clone(),
+        revoked_sessions,
+    });
+    Ok(HttpResponse::Ok().json(json!({ "revoked_sessions": revoked_sessions })))
+}
+
+/// Message with a link for `user` to choose a new password.
+fn password_reset_email(user: &User) -> AuthResult<Email> {
+    let cfg = get_config();
+    let token = issue_email_token(EmailTokenPurpose::ResetPassword, user)?;
+    let link = format!("{}/reset-password?token={token}", cfg.mail_link_base_url.trim_end_matches('/'));
+    Ok(Email {
+        to: user.email.clone(),
+        subject: "Reset your password".into(),
+        body: format!(
+            "Hello {},\n\nsomeone asked to reset the password of your account. To choose a new one, \
+             open this link:\n\n{link}\n\nThe link is valid for {} and works once. If you did not \
+             ask for this, ignore this message; your password stays unchanged.\n",
+            user.username,
+            validity(cfg.password_reset_ttl_secs)
+        ),
+    })
+}
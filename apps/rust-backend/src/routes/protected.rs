@@ -1,6 +1,23 @@
 use actix_web::{get, HttpResponse, Responder};
 
+use crate::middleware::rbac::{RequireAnyRole, RequirePermission, RequireRole};
+
 #[get("/protected/health")]
 pub async fn health() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
 }
+
+#[get("/admin/health", wrap = "RequireRole(\"admin\")")]
+pub async fn admin_health() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({"status": "ok", "scope": "admin"}))
+}
+
+#[get("/staff/health", wrap = "RequireAnyRole([\"admin\", \"support\"])")]
+pub async fn staff_health() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({"status": "ok", "scope": "staff"}))
+}
+
+#[get("/protected/health/details", wrap = "RequirePermission(\"health:read\")")]
+pub async fn health_details() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({"status": "ok", "permission": "health:read"}))
+}
@@ -0,0 +1,106 @@
+use actix_web::web::ReqData;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::events::{self, AuthEvent};
+use crate::config::get_config;
+use crate::middleware::rate_limit::RateLimit;
+use crate::recovery::{hash_code, regenerate_codes, RecoveryCodeRepository};
+use crate::routes::auth::start_session;
+use crate::routes::mfa::check_code;
+use crate::sessions::{revoke_user_sessions, SessionRepository};
+use crate::users::{check_password, UserRepository};
+
+// No Debug derive: the payload carries a credential
+#[derive(Deserialize)]
+pub struct RecoveryLoginPayload {
+    /// Username or email address
+    #[serde(alias = "email")]
+    pub username: String,
+    pub code: String,
+}
+
+// No Debug derive: the payload carries a credential
+#[derive(Deserialize)]
+pub struct RegenerateCodesPayload {
+    pub password: Option<String>,
+    /// A current TOTP code, accepted in place of the password when TOTP is enabled
+    pub code: Option<String>,
+}
+
+/// Issues a new set of recovery codes, invalidating every earlier one. The codes
+/// are returned only here; they are stored hashed. Takes the password or a TOTP
+/// code, since the codes replace both: an access token alone must not get them.
+#[post("", wrap = "RateLimit::reauthentication()")]
+pub async fn regenerate_recovery_codes(
+    user: ReqData<AuthenticatedUser>,
+    users: web::Data<dyn UserRepository>,
+    recovery: web::Data<dyn RecoveryCodeRepository>,
+    payload: web::Json<RegenerateCodesPayload>,
+) -> AuthResult<HttpResponse> {
+    let cfg = get_config();
+    let account = users.find_by_id(&user.user_id).await?.ok_or(AuthError::UserNotFound)?;
+    match (&payload.password, &payload.code) {
+        (Some(password), _) => {
+            if !check_password(&account, password).await? {
+                return Err(AuthError::InvalidCredentials);
+            }
+        }
+        (None, Some(code)) if account.totp_enabled => {
+            let step = check_code(&account, code)?;
+            if !users.record_totp_step(&account.id, step).await? {
+                return Err(AuthError::InvalidMfaCode);
+            }
+        }
+        _ => return Err(AuthError::MissingCredentials),
+    }
+
+    let codes = regenerate_codes(recovery.get_ref(), &user.user_id, cfg.recovery_code_count).await?;
+    events::emit(AuthEvent::RecoveryCodesRegenerated { sub: user.user_id.clone() });
+    Ok(HttpResponse::Ok().json(json!({ "codes": codes })))
+}
+
+#[get("")]
+pub async fn recovery_codes_status(
+    user: ReqData<AuthenticatedUser>,
+    recovery: web::Data<dyn RecoveryCodeRepository>,
+) -> AuthResult<HttpResponse> {
+    let remaining = recovery.remaining(&user.user_id).await?;
+    Ok(HttpResponse::Ok().json(json!({ "remaining": remaining })))
+}
+
+/// Signs in with a recovery code in place of the password and any second factor.
+/// The code is consumed and every other session of the account is ended, since
+/// whoever held the lost credentials may still be signed in.
+#[post("/login", wrap = "RateLimit::login()")]
+pub async fn recovery_login(
+    req: HttpRequest,
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    recovery: web::Data<dyn RecoveryCodeRepository>,
+    payload: web::Json<RecoveryLoginPayload>,
+) -> AuthResult<HttpResponse> {
+    // Unknown account, disabled account and wrong code are indistinguishable
+    let user = match users.find_by_login(&payload.username).await? {
+        Some(user) if !user.disabled => user,
+        _ => return Err(AuthError::InvalidCredentials),
+    };
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    if !recovery.consume(&user.id, &hash_code(&payload.code), now).await? {
+        return Err(AuthError::InvalidCredentials);
+    }
+
+    let revoked_sessions = revoke_user_sessions(sessions.get_ref(), &user.id, None).await?;
+    let remaining = recovery.remaining(&user.id).await?;
+    events::emit(AuthEvent::RecoveryCodeUsed {
+        sub: user.id.clone(),
+        remaining,
+        revoked_sessions,
+    });
+
+    start_session(&req, sessions.get_ref(), &user).await
+}
@@ -0,0 +1,60 @@
+use actix_web::web::ReqData;
+use actix_web::{delete, get, web, HttpResponse};
+use serde_json::json;
+
+use crate::auth::claims::AuthenticatedUser;
+use crate::auth::error::{AuthError, AuthResult};
+use crate::sessions::{active_sessions, revoke_session, revoke_user_sessions, SessionRepository};
+
+/// Lists the caller's signed-in devices; the one making the request is `current`.
+#[get("")]
+pub async fn list_sessions(
+    user: ReqData<AuthenticatedUser>,
+    sessions: web::Data<dyn SessionRepository>,
+) -> AuthResult<HttpResponse> {
+    let listed: Vec<_> = active_sessions(sessions.get_ref(), &user.user_id)
+        .await?
+        .into_iter()
+        .map(|s| {
+            let current = user.session_id.as_deref() == Some(s.id.as_str());
+            json!({
+                "id": s.id,
+                "created_at": s.created_at,
+                "last_refreshed_at": s.last_refreshed_at,
+                "expires_at": s.expires_at,
+                "user_agent": s.user_agent,
+                "ip": s.ip,
+                "current": current,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({ "sessions": listed })))
+}
+
+/// Signs one device out. Other users' sessions are reported as not found.
+#[delete("/{id}")]
+pub async fn delete_session(
+    user: ReqData<AuthenticatedUser>,
+    sessions: web::Data<dyn SessionRepository>,
+    path: web::Path<String>,
+) -> AuthResult<HttpResponse> {
+    let id = path.into_inner();
+    match sessions.find(&id).await? {
+        Some(session) if session.user_id == user.user_id => {
+            revoke_session(sessions.get_ref(), &id).await?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        _ => Err(AuthError::SessionNotFound),
+    }
+}
+
+/// Signs out every device except the one making the request.
+#[delete("")]
+pub async fn delete_other_sessions(
+    user: ReqData<AuthenticatedUser>,
+    sessions: web::Data<dyn SessionRepository>,
+) -> AuthResult<HttpResponse> {
+    let revoked = revoke_user_sessions(sessions.get_ref(), &user.user_id, user.session_id.as_deref()).await?;
+    Ok(HttpResponse::Ok().json(json!({ "revoked": revoked })))
+}
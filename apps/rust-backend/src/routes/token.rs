@@ -0,0 +1,319 @@
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::{post, web, HttpResponse};
+use actix_web_httpauth::extractors::basic::BasicAuth;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::claims::{Actor, AuthenticatedUser};
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::events::{self, AuthEvent};
+use crate::auth::permissions::{self, has_permission};
+use crate::auth::refresh::verify_refresh_token;
+use crate::auth::token::{is_access_token_revoked, issue_client_token, issue_exchanged_token, verify_access_token};
+use crate::clients::{authenticate_client, Client, ClientRepository};
+use crate::config::get_config;
+use crate::sessions::SessionRepository;
+use crate::users::{User, UserRepository};
+
+// No Debug derive: the form may carry a client secret
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub scope: Option<String>,
+    /// `client_secret_post` credentials, for clients that can't send Basic auth
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// Token exchange (RFC 8693): the user to act for, by their token...
+    pub subject_token: Option<String>,
+    pub subject_token_type: Option<String>,
+    /// ...or, for impersonation, by user id or login (an extension to RFC 8693)
+    pub requested_subject: Option<String>,
+    /// Token exchange: the access token of whoever is really acting
+    pub actor_token: Option<String>,
+    pub actor_token_type: Option<String>,
+    pub requested_token_type: Option<String>,
+}
+
+const TOKEN_EXCHANGE_GRANT: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// OAuth 2.0 token endpoint (RFC 6749 section 3.2). Takes form-encoded requests
+/// and answers errors in the OAuth shape rather than the usual envelope.
+#[post("/token")]
+pub async fn issue_token(
+    basic: Option<BasicAuth>,
+    clients: web::Data<dyn ClientRepository>,
+    users: web::Data<dyn UserRepository>,
+    form: web::Form<TokenRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let result = match form.grant_type.as_deref() {
+        Some("client_credentials") => client_credentials(basic, clients.get_ref(), &form).await,
+        Some(TOKEN_EXCHANGE_GRANT) => token_exchange(users.get_ref(), &form).await,
+        Some(other) => Err(AuthError::UnsupportedGrantType(other.to_string())),
+        None => Err(AuthError::InvalidRequest("missing grant_type".into())),
+    };
+    result.map_err(AuthError::into_oauth_error)
+}
+
+/// Client credentials grant (RFC 6749 section 4.4): a token for the client itself,
+/// with no user behind it.
+async fn client_credentials(
+    basic: Option<BasicAuth>,
+    clients: &dyn ClientRepository,
+    form: &TokenRequest,
+) -> AuthResult<HttpResponse> {
+    let client =
+        authenticate_client_request(basic, clients, form.client_id.as_deref(), form.client_secret.as_deref()).await?;
+    let scope = narrow_scope(&client.scopes, form.scope.as_deref())?;
+    let access_token = issue_client_token(&client.id, &scope)?;
+
+    Ok(token_response(&access_token, &scope, get_config().access_ttl_min, None))
+}
+
+/// Token exchange (RFC 8693): the actor, identified by `actor_token`, gets a
+/// short-lived token for another user whose `act` claim names the actor.
+///
+/// With `subject_token` this is delegation: the actor holds a token of the user
+/// and gets one carrying at most its scope. With `requested_subject` it is
+/// impersonation, e.g. a support agent acting as a customer, and the actor needs
+/// `IMPERSONATION_PERMISSION`. Users who hold that permission themselves can't be
+/// impersonated, so it never escalates to another administrator, and role guards
+/// turn exchanged tokens away, so neither do the subject's roles.
+async fn token_exchange(users: &dyn UserRepository, form: &TokenRequest) -> AuthResult<HttpResponse> {
+    let cfg = get_config();
+    for token_type in [&form.subject_token_type, &form.actor_token_type, &form.requested_token_type] {
+        if let Some(token_type) = token_type
+            && token_type != ACCESS_TOKEN_TYPE
+        {
+            return Err(AuthError::InvalidRequest(format!("unsupported token type: {token_type}")));
+        }
+    }
+
+    let actor_token = form
+        .actor_token
+        .as_deref()
+        .ok_or_else(|| AuthError::InvalidRequest("missing actor_token".into()))?;
+    let actor = active_token(actor_token).ok_or_else(|| AuthError::InvalidRequest("actor_token is not active".into()))?;
+    // A token from an exchange can't be exchanged again as the actor
+    if actor.is_delegated() {
+        return Err(AuthError::Forbidden("actor_token was itself obtained by token exchange".into()));
+    }
+
+    let (subject, granted, prior_actor, session_id) = match (&form.subject_token, &form.requested_subject) {
+        (Some(subject_token), None) => {
+            let subject = active_token(subject_token)
+                .filter(|s| !s.is_machine())
+                .ok_or_else(|| AuthError::InvalidRequest("subject_token is not an active user token".into()))?;
+            let user = active_user(users, &subject.user_id).await?;
+            (user, subject.scope, subject.actor.map(Box::new), subject.session_id)
+        }
+        (None, Some(requested)) => {
+            if !actor.has_permission(&cfg.impersonation_permission) {
+                return Err(AuthError::Forbidden(format!(
+                    "requires permission: {}",
+                    cfg.impersonation_permission
+                )));
+            }
+            let user = match users.find_by_id(requested).await? {
+                Some(user) => Some(user),
+                None => users.find_by_login(requested).await?,
+            };
+            let user = active_user(users, &user.ok_or(AuthError::UserNotFound)?.id).await?;
+            if has_permission(&user.permissions, &cfg.impersonation_permission) {
+                return Err(AuthError::Forbidden("this user can't be impersonated".into()));
+            }
+            let permissions = user.permissions.clone();
+            (user, permissions, None, None)
+        }
+        _ => {
+            return Err(AuthError::InvalidRequest(
+                "exactly one of subject_token and requested_subject is required".into(),
+            ));
+        }
+    };
+
+    let scope = narrow_scope(&granted, form.scope.as_deref())?;
+    let act = Actor {
+        sub: (!actor.is_machine()).then(|| actor.user_id.clone()),
+        client_id: actor.client_id.clone(),
+        act: prior_actor,
+    };
+    let access_token = issue_exchanged_token(&subject.id, &subject.roles, &scope, session_id.as_deref(), &act)?;
+
+    events::emit(AuthEvent::TokenExchanged {
+        sub: subject.id.clone(),
+        actor: act,
+        impersonation: form.requested_subject.is_some(),
+        scope: permissions::to_scope(&scope),
+    });
+    Ok(token_response(&access_token, &scope, cfg.token_exchange_ttl_min, Some(ACCESS_TOKEN_TYPE)))
+}
+
+/// The verified, unrevoked access token `token`, if it is one.
+fn active_token(token: &str) -> Option<AuthenticatedUser> {
+    verify_access_token(token).ok().filter(|u| !is_access_token_revoked(u))
+}
+
+async fn active_user(users: &dyn UserRepository, id: &str) -> AuthResult<User> {
+    match users.find_by_id(id).await? {
+        Some(user) if !user.disabled => Ok(user),
+        Some(_) => Err(AuthError::Forbidden("user is disabled".into())),
+        None => Err(AuthError::UserNotFound),
+    }
+}
+
+/// Authenticates the calling client with `client_secret_basic` or
+/// `client_secret_post` (`client_id` and `client_secret` from the form), whichever
+/// it used.
+pub async fn authenticate_client_request(
+    basic: Option<BasicAuth>,
+    clients: &dyn ClientRepository,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> AuthResult<Client> {
+    let (id, secret) = match (basic, client_id, client_secret) {
+        // Section 2.3: a client must not use more than one authentication method
+        (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+            return Err(AuthError::InvalidRequest("multiple client authentication methods".into()));
+        }
+        // Section 2.3.1: both parts are form-urlencoded inside the Basic credentials
+        (Some(basic), None, None) => (
+            form_decode(basic.user_id()),
+            form_decode(basic.password().unwrap_or_default()),
+        ),
+        (None, Some(id), Some(secret)) => (id.to_string(), secret.to_string()),
+        _ => return Err(AuthError::InvalidClient),
+    };
+    authenticate_client(clients, &id, &secret).await
+}
+
+/// The scope to grant: all of `granted` when none is asked for, otherwise the
+/// requested subset. Asking for more than `granted` covers fails.
+fn narrow_scope(granted: &[String], requested: Option<&str>) -> AuthResult<Vec<String>> {
+    let Some(requested) = requested.filter(|s| !s.trim().is_empty()) else {
+        return Ok(granted.to_vec());
+    };
+    let requested = permissions::from_scope(requested);
+    if let Some(denied) = requested.iter().find(|s| !has_permission(granted, s)) {
+        return Err(AuthError::InvalidScope(format!("not granted: {denied}")));
+    }
+    Ok(requested)
+}
+
+/// Successful token response (RFC 6749 section 5.1), never cached.
+/// `issued_token_type` is set for token exchange (RFC 8693 section 2.2.1).
+pub fn token_response(
+    access_token: &str,
+    scope: &[String],
+    ttl_min: i64,
+    issued_token_type: Option<&str>,
+) -> HttpResponse {
+    let mut body = json!({
+        "access_token": access_token,
+        "token_type": "Bearer",
+        "expires_in": ttl_min * 60,
+        "scope": permissions::to_scope(scope),
+    });
+    if let Some(issued_token_type) = issued_token_type {
+        body["issued_token_type"] = json!(issued_token_type);
+    }
+    HttpResponse::Ok().insert_header((CACHE_CONTROL, "no-store")).json(body)
+}
+
+// No Debug derive: the form carries a token and possibly a client secret
+#[derive(Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    /// `access_token` or `refresh_token`; only decides which kind is tried first
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Token introspection (RFC 7662) for services that can't verify tokens locally.
+/// Callers authenticate as registered clients. Any token that is malformed,
+/// expired, revoked or belongs to an ended session is reported as `active: false`,
+/// with no further detail.
+#[post("/introspect")]
+pub async fn introspect(
+    basic: Option<BasicAuth>,
+    clients: web::Data<dyn ClientRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    form: web::Form<IntrospectionRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let result = async {
+        authenticate_client_request(
+            basic,
+            clients.get_ref(),
+            form.client_id.as_deref(),
+            form.client_secret.as_deref(),
+        )
+        .await?;
+
+        let refresh_first = form.token_type_hint.as_deref() == Some("refresh_token");
+        let introspected = if refresh_first {
+            match introspect_refresh_token(&form.token, sessions.get_ref()).await? {
+                Some(found) => Some(found),
+                None => introspect_access_token(&form.token),
+            }
+        } else {
+            match introspect_access_token(&form.token) {
+                Some(found) => Some(found),
+                None => introspect_refresh_token(&form.token, sessions.get_ref()).await?,
+            }
+        };
+
+        Ok(HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .json(introspected.unwrap_or_else(|| json!({ "active": false }))))
+    };
+    result.await.map_err(AuthError::into_oauth_error)
+}
+
+fn introspect_access_token(token: &str) -> Option<Value> {
+    let user = active_token(token)?;
+    let mut response = json!({
+        "active": true,
+        "token_type": "Bearer",
+        "sub": (!user.is_machine()).then_some(&user.user_id),
+        "client_id": user.client_id,
+        "scope": permissions::to_scope(&user.scope),
+        "exp": user.exp,
+        "jti": user.jti,
+        "sid": user.session_id,
+        "act": user.actor,
+    });
+    // Members that don't apply to this token are left out rather than null
+    if let Some(members) = response.as_object_mut() {
+        members.retain(|_, v| !v.is_null());
+    }
+    Some(response)
+}
+
+/// Refresh tokens are active only while unrotated, unrevoked and backed by a
+/// stored session, the same conditions `/refresh` enforces. `token_type` is left
+/// out: RFC 7662 takes its values from RFC 6749 section 5.1, which only names the
+/// type of access tokens, so a caller that sees `Bearer` knows it has one.
+async fn introspect_refresh_token(token: &str, sessions: &dyn SessionRepository) -> AuthResult<Option<Value>> {
+    let Ok(claims) = verify_refresh_token(token) else {
+        return Ok(None);
+    };
+    let session = sessions.find(&claims.family_id).await?;
+    if session.is_none_or(|s| s.user_id != claims.sub) {
+        return Ok(None);
+    }
+    Ok(Some(json!({
+        "active": true,
+        "sub": claims.sub,
+        "exp": claims.exp,
+        "iat": claims.iat,
+        "jti": claims.jti,
+        "sid": claims.family_id,
+    })))
+}
+
+fn form_decode(value: &str) -> String {
+    percent_decode_str(&value.replace('+', " ")).decode_utf8_lossy().into_owned()
+}
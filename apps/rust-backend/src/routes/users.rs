@@ -0,0 +1,25 @@
+use actix_web::{post, web, HttpResponse};
+use serde_json::json;
+
+use crate::auth::error::AuthResult;
+use crate::auth::events::{self, AuthEvent};
+use crate::sessions::{revoke_user_sessions, SessionRepository};
+use crate::users::UserRepository;
+
+/// Disables an account and ends its sessions, so it is locked out now rather than
+/// when its access tokens expire.
+#[post("/{id}/disable")]
+pub async fn disable_user(
+    users: web::Data<dyn UserRepository>,
+    sessions: web::Data<dyn SessionRepository>,
+    path: web::Path<String>,
+) -> AuthResult<HttpResponse> {
+    users.disable(&path).await?;
+
+    let revoked_sessions = revoke_user_sessions(sessions.get_ref(), &path, None).await?;
+    events::emit(AuthEvent::UserDisabled {
+        sub: path.into_inner(),
+        revoked_sessions,
+    });
+    Ok(HttpResponse::Ok().json(json!({ "disabled": true, "revoked_sessions": revoked_sessions })))
+}
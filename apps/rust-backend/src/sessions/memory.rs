@@ -0,0 +1,74 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::auth::error::AuthResult;
+use crate::sessions::{ClientInfo, Session, SessionRepository};
+
+/// Process-local repository; state is lost on restart. Meant for tests and quick demos.
+#[derive(Debug, Default)]
+pub struct InMemorySessionRepository {
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl InMemorySessionRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionRepository for InMemorySessionRepository {
+    async fn create(&self, session: &Session) -> AuthResult<()> {
+        let mut sessions = self.sessions.write().expect("session repository lock");
+        sessions.insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> AuthResult<Option<Session>> {
+        Ok(self.sessions.read().expect("session repository lock").get(id).cloned())
+    }
+
+    async fn touch(
+        &self,
+        id: &str,
+        previous_jti: &str,
+        refresh_jti: &str,
+        now: i64,
+        expires_at: i64,
+        client: &ClientInfo,
+    ) -> AuthResult<bool> {
+        let mut sessions = self.sessions.write().expect("session repository lock");
+        match sessions.get_mut(id) {
+            Some(session) if session.refresh_jti == previous_jti => {
+                session.refresh_jti = refresh_jti.to_string();
+                session.last_refreshed_at = now;
+                session.expires_at = expires_at;
+                session.user_agent = client.user_agent.clone();
+                session.ip = client.ip.clone();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn list_for_user(&self, user_id: &str, now: i64) -> AuthResult<Vec<Session>> {
+        let mut sessions = self.sessions.write().expect("session repository lock");
+        sessions.retain(|_, s| s.expires_at > now);
+
+        let mut listed: Vec<Session> = sessions
+            .values()
+            .filter(|s| s.user_id == user_id)
+            .cloned()
+            .collect();
+        listed.sort_by_key(|s| Reverse(s.last_refreshed_at));
+        Ok(listed)
+    }
+
+    async fn delete(&self, id: &str) -> AuthResult<()> {
+        self.sessions.write().expect("session repository lock").remove(id);
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use actix_web::http::header::USER_AGENT;
+use actix_web::{web, HttpRequest};
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::auth::error::AuthResult;
+use crate::auth::refresh::revoke_refresh_family;
+use crate::auth::revocation::refresh_family_revocations;
+use crate::auth::token::revoke_user_access_tokens;
+use crate::storage::StorageBackend;
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemorySessionRepository;
+pub use sqlite::SqliteSessionRepository;
+
+/// Longest user agent kept; anything beyond is noise or abuse
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// A signed-in device. The id is the refresh token family id (`fid`), so a session
+/// lives exactly as long as its chain of rotated refresh tokens.
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    /// `jti` of the family's newest refresh token, the only one that may be rotated
+    #[serde(skip)]
+    pub refresh_jti: String,
+    pub created_at: i64,
+    pub last_refreshed_at: i64,
+    /// Expiry of the newest refresh token in the family
+    pub expires_at: i64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl Session {
+    pub fn new(id: String, user_id: String, refresh_jti: String, expires_at: i64, client: ClientInfo) -> Self {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        Self {
+            id,
+            user_id,
+            refresh_jti,
+            created_at: now,
+            last_refreshed_at: now,
+            expires_at,
+            user_agent: client.user_agent,
+            ip: client.ip,
+        }
+    }
+}
+
+/// Where a request came from, as recorded on its session. Informational only: the IP
+/// honours `Forwarded` / `X-Forwarded-For`, which clients can set themselves.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+        let ip = req.connection_info().realip_remote_addr().map(str::to_string);
+        Self { user_agent, ip }
+    }
+}
+
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn create(&self, session: &Session) -> AuthResult<()>;
+
+    async fn find(&self, id: &str) -> AuthResult<Option<Session>>;
+
+    /// Records a refresh from `previous_jti` to `refresh_jti`: bumps
+    /// `last_refreshed_at` and `expires_at` and replaces the client details with the
+    /// refreshing client's. Returns `false`, changing nothing, unless `previous_jti`
+    /// is the session's current token; the check and the write are one step, so
+    /// two refreshes with the same token can't both succeed.
+    async fn touch(
+        &self,
+        id: &str,
+        previous_jti: &str,
+        refresh_jti: &str,
+        now: i64,
+        expires_at: i64,
+        client: &ClientInfo,
+    ) -> AuthResult<bool>;
+
+    /// Sessions of `user_id` that have not expired as of `now`, newest first.
+    async fn list_for_user(&self, user_id: &str, now: i64) -> AuthResult<Vec<Session>>;
+
+    async fn delete(&self, id: &str) -> AuthResult<()>;
+}
+
+/// Builds the repository for the configured storage backend.
+pub fn repository(storage: &StorageBackend) -> web::Data<dyn SessionRepository> {
+    let repo: Arc<dyn SessionRepository> = match storage {
+        StorageBackend::Memory => Arc::new(InMemorySessionRepository::new()),
+        StorageBackend::Sqlite(pool) => Arc::new(SqliteSessionRepository::new(pool.clone())),
+    };
+    web::Data::from(repo)
+}
+
+/// Ends a session: its refresh tokens stop working immediately, and so do access
+/// tokens issued to it (see `auth::token::is_access_token_revoked`).
+pub async fn revoke_session(repo: &dyn SessionRepository, id: &str) -> AuthResult<()> {
+    revoke_refresh_family(id);
+    repo.delete(id).await
+}
+
+/// Live sessions of `user_id`. Sessions whose family was revoked behind the
+/// repository's back, e.g. by refresh token reuse detection, are cleaned up here.
+pub async fn active_sessions(repo: &dyn SessionRepository, user_id: &str) -> AuthResult<Vec<Session>> {
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let mut sessions = Vec::new();
+    for session in repo.list_for_user(user_id, now).await? {
+        if refresh_family_revocations().is_revoked(&session.id) {
+            repo.delete(&session.id).await?;
+        } else {
+            sessions.push(session);
+        }
+    }
+    Ok(sessions)
+}
+
+/// Ends every live session of `user_id` except `keep`, returning how many ended.
+/// Sessionless tokens issued for the user, such as impersonation tokens, are
+/// revoked as well.
+pub async fn revoke_user_sessions(
+    repo: &dyn SessionRepository,
+    user_id: &str,
+    keep: Option<&str>,
+) -> AuthResult<usize> {
+    revoke_user_access_tokens(user_id);
+    let mut revoked = 0;
+    for session in active_sessions(repo, user_id).await? {
+        if keep != Some(session.id.as_str()) {
+            revoke_session(repo, &session.id).await?;
+            revoked += 1;
+        }
+    }
+    Ok(revoked)
+}
@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::auth::error::AuthResult;
+use crate::sessions::{ClientInfo, Session, SessionRepository};
+use crate::storage::storage_error;
+
+const SESSION_COLUMNS: &str =
+    "id, user_id, refresh_jti, created_at, last_refreshed_at, expires_at, user_agent, ip";
+
+/// SQLite-backed repository over the pool opened by `storage::connect_sqlite`.
+#[derive(Debug, Clone)]
+pub struct SqliteSessionRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteSessionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SqliteSessionRepository {
+    async fn create(&self, session: &Session) -> AuthResult<()> {
+        sqlx::query(
+            "INSERT INTO sessions (id, user_id, refresh_jti, created_at, last_refreshed_at, expires_at, \
+             user_agent, ip) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .bind(&session.id)
+        .bind(&session.user_id)
+        .bind(&session.refresh_jti)
+        .bind(session.created_at)
+        .bind(session.last_refreshed_at)
+        .bind(session.expires_at)
+        .bind(&session.user_agent)
+        .bind(&session.ip)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn find(&self, id: &str) -> AuthResult<Option<Session>> {
+        let sql = format!("SELECT {SESSION_COLUMNS} FROM sessions WHERE id = ?1");
+        let row = sqlx::query(&sql)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        row.as_ref().map(row_to_session).transpose()
+    }
+
+    async fn touch(
+        &self,
+        id: &str,
+        previous_jti: &str,
+        refresh_jti: &str,
+        now: i64,
+        expires_at: i64,
+        client: &ClientInfo,
+    ) -> AuthResult<bool> {
+        let result = sqlx::query(
+            "UPDATE sessions SET refresh_jti = ?3, last_refreshed_at = ?4, expires_at = ?5, \
+             user_agent = ?6, ip = ?7 WHERE id = ?1 AND refresh_jti = ?2",
+        )
+        .bind(id)
+        .bind(previous_jti)
+        .bind(refresh_jti)
+        .bind(now)
+        .bind(expires_at)
+        .bind(&client.user_agent)
+        .bind(&client.ip)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_for_user(&self, user_id: &str, now: i64) -> AuthResult<Vec<Session>> {
+        sqlx::query("DELETE FROM sessions WHERE expires_at <= ?1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        let sql = format!(
+            "SELECT {SESSION_COLUMNS} FROM sessions WHERE user_id = ?1 ORDER BY last_refreshed_at DESC"
+        );
+        let rows = sqlx::query(&sql)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        rows.iter().map(row_to_session).collect()
+    }
+
+    async fn delete(&self, id: &str) -> AuthResult<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+fn row_to_session(row: &SqliteRow) -> AuthResult<Session> {
+    Ok(Session {
+        id: row.try_get("id").map_err(storage_error)?,
+        user_id: row.try_get("user_id").map_err(storage_error)?,
+        refresh_jti: row.try_get("refresh_jti").map_err(storage_error)?,
+        created_at: row.try_get("created_at").map_err(storage_error)?,
+        last_refreshed_at: row.try_get("last_refreshed_at").map_err(storage_error)?,
+        expires_at: row.try_get("expires_at").map_err(storage_error)?,
+        user_agent: row.try_get("user_agent").map_err(storage_error)?,
+        ip: row.try_get("ip").map_err(storage_error)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::connect_sqlite;
+    use crate::users::{SqliteUserRepository, User, UserRepository};
+
+    #[actix_web::test]
+    async fn touch_only_rotates_the_current_token() {
+        let path = std::env::temp_dir().join(format!("sessions-{}.db", uuid::Uuid::new_v4()));
+        let pool = connect_sqlite(&format!("sqlite://{}", path.display())).await.unwrap();
+        let user = User::new("ada".into(), "ada@example.com".into(), "hash".into(), Vec::new(), Vec::new());
+        let user = SqliteUserRepository::new(pool.clone()).create(user).await.unwrap();
+        let repo = SqliteSessionRepository::new(pool);
+        let session = Session::new("family".into(), user.id, "first".into(), 100, ClientInfo::default());
+        repo.create(&session).await.unwrap();
+
+        let client = ClientInfo::default();
+        assert!(repo.touch("family", "first", "second", 10, 200, &client).await.unwrap());
+        // The rotated token no longer matches, however often it is presented
+        assert!(!repo.touch("family", "first", "third", 20, 300, &client).await.unwrap());
+
+        let stored = repo.find("family").await.unwrap().unwrap();
+        assert_eq!(stored.refresh_jti, "second");
+        assert_eq!(stored.expires_at, 200);
+    }
+}
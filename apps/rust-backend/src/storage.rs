@@ -0,0 +1,49 @@
+use std::str::FromStr;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::config::get_config;
+
+/// Backend selected by `USER_REPOSITORY`. Every repository is built from the same
+/// backend, so users, sessions and the rest live in one database and share a pool.
+#[derive(Debug, Clone)]
+pub enum StorageBackend {
+    /// Process-local maps; state is lost on restart
+    Memory,
+    Sqlite(SqlitePool),
+}
+
+impl StorageBackend {
+    pub async fn from_config() -> AuthResult<Self> {
+        let cfg = get_config();
+        match cfg.user_repository.as_str() {
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Ok(StorageBackend::Sqlite(connect_sqlite(&cfg.database_url).await?)),
+        }
+    }
+}
+
+/// Opens the pool and applies the migrations under `migrations/`, which are embedded
+/// at compile time, so only a writable file path is needed.
+pub async fn connect_sqlite(database_url: &str) -> AuthResult<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(database_url)
+        .map_err(storage_error)?
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
+        .map_err(storage_error)?;
+
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .map_err(|e| AuthError::Storage(format!("migration error: {e}")))?;
+
+    Ok(pool)
+}
+
+pub fn storage_error(e: impl std::fmt::Display) -> AuthError {
+    AuthError::Storage(e.to_string())
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::users::{User, UserRepository};
+
+/// Process-local repository; state is lost on restart. Meant for tests and quick demos.
+#[derive(Debug, Default)]
+pub struct InMemoryUserRepository {
+    users: RwLock<HashMap<String, User>>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn find_by<F>(&self, pred: F) -> Option<User>
+    where
+        F: Fn(&User) -> bool,
+    {
+        let users = self.users.read().expect("user repository lock");
+        users.values().find(|u| pred(u)).cloned()
+    }
+
+    /// Applies `change` to the stored user under the write lock.
+    fn modify<F>(&self, id: &str, change: F) -> AuthResult<()>
+    where
+        F: FnOnce(&mut User),
+    {
+        let mut users = self.users.write().expect("user repository lock");
+        let stored = users.get_mut(id).ok_or(AuthError::UserNotFound)?;
+        change(stored);
+        stored.updated_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn find_by_id(&self, id: &str) -> AuthResult<Option<User>> {
+        Ok(self.users.read().expect("user repository lock").get(id).cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> AuthResult<Option<User>> {
+        Ok(self.find_by(|u| u.email.eq_ignore_ascii_case(email)))
+    }
+
+    async fn find_by_username(&self, username: &str) -> AuthResult<Option<User>> {
+        Ok(self.find_by(|u| u.username.eq_ignore_ascii_case(username)))
+    }
+
+    async fn create(&self, user: User) -> AuthResult<User> {
+        let mut users = self.users.write().expect("user repository lock");
+        let taken = users.values().any(|u| {
+            u.id == user.id
+                || u.email.eq_ignore_ascii_case(&user.email)
+                || u.username.eq_ignore_ascii_case(&user.username)
+        });
+        if taken {
+            return Err(AuthError::UserAlreadyExists);
+        }
+        users.insert(user.id.clone(), user.clone());
+        Ok(user)
+    }
+
+    async fn update(&self, user: &User) -> AuthResult<()> {
+        let mut users = self.users.write().expect("user repository lock");
+        let taken = users.values().any(|u| {
+            u.id != user.id
+                && (u.email.eq_ignore_ascii_case(&user.email)
+                    || u.username.eq_ignore_ascii_case(&user.username))
+        });
+        if taken {
+            return Err(AuthError::UserAlreadyExists);
+        }
+        let stored = users.get_mut(&user.id).ok_or(AuthError::UserNotFound)?;
+        *stored = User {
+            magic_link_generation: stored.magic_link_generation,
+            updated_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+            ..user.clone()
+        };
+        Ok(())
+    }
+
+    async fn disable(&self, id: &str) -> AuthResult<()> {
+        self.modify(id, |user| user.disabled = true)
+    }
+
+    async fn set_password_hash(&self, id: &str, password_hash: &str) -> AuthResult<()> {
+        self.modify(id, |user| user.password_hash = password_hash.to_string())
+    }
+
+    async fn mark_email_verified(&self, id: &str) -> AuthResult<()> {
+        self.modify(id, |user| user.email_verified = true)
+    }
+
+    async fn record_totp_step(&self, id: &str, step: i64) -> AuthResult<bool> {
+        let mut users = self.users.write().expect("user repository lock");
+        let stored = users.get_mut(id).ok_or(AuthError::UserNotFound)?;
+        if stored.totp_last_step >= step {
+            return Ok(false);
+        }
+        stored.totp_last_step = step;
+        stored.updated_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        Ok(true)
+    }
+
+    async fn advance_magic_link_generation(&self, id: &str, generation: i64) -> AuthResult<bool> {
+        let mut users = self.users.write().expect("user repository lock");
+        let stored = users.get_mut(id).ok_or(AuthError::UserNotFound)?;
+        if stored.magic_link_generation != generation {
+            return Ok(false);
+        }
+        stored.magic_link_generation = generation + 1;
+        stored.updated_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        Ok(true)
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::sync::Arc;
+
+use actix_web::web;
+use async_trait::async_trait;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::auth::password::{hash_password, verify_dummy_password, verify_password};
+use crate::config::get_config;
+use crate::storage::StorageBackend;
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemoryUserRepository;
+pub use sqlite::SqliteUserRepository;
+
+#[derive(Clone)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    /// Whether the user has shown they receive mail at `email`
+    pub email_verified: bool,
+    pub password_hash: String,
+    pub roles: Vec<String>,
+    /// Granted permissions, carried in the access token `scope` (see `auth::permissions`)
+    pub permissions: Vec<String>,
+    pub disabled: bool,
+    /// TOTP secret sealed by `auth::mfa::seal_secret`; present but not enabled while
+    /// enrollment awaits confirmation
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    /// Last TOTP time step accepted, so a code cannot be replayed
+    pub totp_last_step: i64,
+    /// Number of magic links redeemed; a link is only good at the value it was issued at
+    pub magic_link_generation: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl User {
+    pub fn new(
+        username: String,
+        email: String,
+        password_hash: String,
+        roles: Vec<String>,
+        permissions: Vec<String>,
+    ) -> Self {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            username,
+            email,
+            email_verified: false,
+            password_hash,
+            roles,
+            permissions,
+            disabled: false,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: 0,
+            magic_link_generation: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("username", &self.username)
+            .field("email", &self.email)
+            .field("email_verified", &self.email_verified)
+            .field("roles", &self.roles)
+            .field("permissions", &self.permissions)
+            .field("disabled", &self.disabled)
+            .field("totp_enabled", &self.totp_enabled)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .finish()
+    }
+}
+
+/// Server-side source of truth for accounts, their roles and permissions.
+///
+/// Handlers receive it as `web::Data<dyn UserRepository>`. Username and email
+/// lookups are case-insensitive in every implementation.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_by_id(&self, id: &str) -> AuthResult<Option<User>>;
+
+    async fn find_by_email(&self, email: &str) -> AuthResult<Option<User>>;
+
+    async fn find_by_username(&self, username: &str) -> AuthResult<Option<User>>;
+
+    /// Fails with `UserAlreadyExists` when the username or email is taken.
+    async fn create(&self, user: User) -> AuthResult<User>;
+
+    /// Replaces every mutable field of the stored user with `user`'s, except
+    /// `magic_link_generation`, which only `advance_magic_link_generation` moves.
+    /// Handlers that change a single field use the narrow writes below instead, so a
+    /// concurrent handler's change to another field isn't undone from a stale copy.
+    async fn update(&self, user: &User) -> AuthResult<()>;
+
+    /// Stops the account from signing in or refreshing; its data stays.
+    async fn disable(&self, id: &str) -> AuthResult<()>;
+
+    async fn set_password_hash(&self, id: &str, password_hash: &str) -> AuthResult<()>;
+
+    async fn mark_email_verified(&self, id: &str) -> AuthResult<()>;
+
+    /// Moves `totp_last_step` up to `step` if it is still below it. Returns `false`
+    /// when it is not, i.e. a code of that step was accepted in the meantime; the
+    /// check and the write are one step, so a code can't be used twice concurrently.
+    async fn record_totp_step(&self, id: &str, step: i64) -> AuthResult<bool>;
+
+    /// Moves `magic_link_generation` from `generation` to the next value. Returns
+    /// `false` when it is no longer `generation`, i.e. a link of that generation was
+    /// redeemed already.
+    async fn advance_magic_link_generation(&self, id: &str, generation: i64) -> AuthResult<bool>;
+
+    /// Looks a user up by email, falling back to username.
+    async fn find_by_login(&self, login: &str) -> AuthResult<Option<User>> {
+        match self.find_by_email(login).await? {
+            Some(user) => Ok(Some(user)),
+            None => self.find_by_username(login).await,
+        }
+    }
+}
+
+/// Builds the repository for the configured storage backend.
+pub fn repository(storage: &StorageBackend) -> web::Data<dyn UserRepository> {
+    let repo: Arc<dyn UserRepository> = match storage {
+        StorageBackend::Memory => Arc::new(InMemoryUserRepository::new()),
+        StorageBackend::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool.clone())),
+    };
+    web::Data::from(repo)
+}
+
+/// Checks a username/email + password pair. Unknown, disabled and wrong-password
+/// cases all collapse into `InvalidCredentials`.
+pub async fn authenticate(repo: &dyn UserRepository, login: &str, password: &str) -> AuthResult<User> {
+    let user = repo.find_by_login(login).await?;
+    let password = password.to_string();
+
+    // Argon2 is deliberately slow; keep it off the async worker
+    let Some(user) = user else {
+        let _ = web::block(move || verify_dummy_password(&password)).await;
+        return Err(AuthError::InvalidCredentials);
+    };
+
+    if !check_password(&user, &password).await? || user.disabled {
+        return Err(AuthError::InvalidCredentials);
+    }
+    Ok(user)
+}
+
+/// Whether `password` is `user`'s, verified off the async worker.
+pub async fn check_password(user: &User, password: &str) -> AuthResult<bool> {
+    let (password, hash) = (password.to_string(), user.password_hash.clone());
+    web::block(move || verify_password(&password, &hash))
+        .await
+        .map_err(|e| AuthError::Internal(format!("blocking error: {e}")))?
+}
+
+/// Creates the bootstrap account from `SEED_USER_*` so a fresh install has someone to log in as.
+pub async fn seed_from_config(repo: &dyn UserRepository) -> AuthResult<()> {
+    let cfg = get_config();
+    let Some(password) = cfg.seed_user_password.as_deref() else {
+        return Ok(());
+    };
+    if repo.find_by_email(&cfg.seed_user_email).await?.is_some() {
+        return Ok(());
+    }
+
+    let username = cfg
+        .seed_user_email
+        .split('@')
+        .next()
+        .unwrap_or(&cfg.seed_user_email)
+        .to_string();
+
+    let mut user = User::new(
+        username,
+        cfg.seed_user_email.clone(),
+        hash_password(password)?,
+        cfg.seed_user_roles.clone(),
+        cfg.seed_user_permissions.clone(),
+    );
+    // The operator chose this address; there is no one to send a link to yet
+    user.email_verified = true;
+    repo.create(user).await?;
+    println!("[auth] seeded user {}", cfg.seed_user_email);
+    Ok(())
+}
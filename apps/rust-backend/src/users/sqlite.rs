@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+
+use crate::auth::error::{AuthError, AuthResult};
+use crate::storage::storage_error;
+use crate::users::{User, UserRepository};
+
+const USER_COLUMNS: &str = "id, username, email, email_verified, password_hash, roles, permissions, \
+     disabled, totp_secret, totp_enabled, totp_last_step, magic_link_generation, created_at, updated_at";
+
+/// SQLite-backed repository over the pool opened by `storage::connect_sqlite`.
+#[derive(Debug, Clone)]
+pub struct SqliteUserRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    async fn find_one(&self, column: &str, value: &str) -> AuthResult<Option<User>> {
+        let sql = format!("SELECT {USER_COLUMNS} FROM users WHERE {column} = ?1");
+        let row = sqlx::query(&sql)
+            .bind(value)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        row.as_ref().map(row_to_user).transpose()
+    }
+
+    /// Writes one column of one user, leaving the others as they are in the table.
+    async fn set_column<T>(&self, id: &str, column: &str, value: T) -> AuthResult<()>
+    where
+        T: for<'q> sqlx::Encode<'q, sqlx::Sqlite> + sqlx::Type<sqlx::Sqlite> + Send,
+    {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let sql = format!("UPDATE users SET {column} = ?2, updated_at = ?3 WHERE id = ?1");
+        let result = sqlx::query(&sql)
+            .bind(id)
+            .bind(value)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(AuthError::UserNotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn find_by_id(&self, id: &str) -> AuthResult<Option<User>> {
+        self.find_one("id", id).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> AuthResult<Option<User>> {
+        self.find_one("email", email).await
+    }
+
+    async fn find_by_username(&self, username: &str) -> AuthResult<Option<User>> {
+        self.find_one("username", username).await
+    }
+
+    async fn create(&self, user: User) -> AuthResult<User> {
+        let roles = serde_json::to_string(&user.roles).map_err(storage_error)?;
+        let permissions = serde_json::to_string(&user.permissions).map_err(storage_error)?;
+        sqlx::query(
+            "INSERT INTO users (id, username, email, email_verified, password_hash, roles, permissions, \
+             disabled, totp_secret, totp_enabled, totp_last_step, magic_link_generation, created_at, \
+             updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )
+        .bind(&user.id)
+        .bind(&user.username)
+        .bind(&user.email)
+        .bind(user.email_verified)
+        .bind(&user.password_hash)
+        .bind(roles)
+        .bind(permissions)
+        .bind(user.disabled)
+        .bind(&user.totp_secret)
+        .bind(user.totp_enabled)
+        .bind(user.totp_last_step)
+        .bind(user.magic_link_generation)
+        .bind(user.created_at)
+        .bind(user.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+        Ok(user)
+    }
+
+    async fn update(&self, user: &User) -> AuthResult<()> {
+        let roles = serde_json::to_string(&user.roles).map_err(storage_error)?;
+        let permissions = serde_json::to_string(&user.permissions).map_err(storage_error)?;
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let result = sqlx::query(
+            "UPDATE users SET username = ?2, email = ?3, email_verified = ?4, password_hash = ?5, \
+             roles = ?6, permissions = ?7, disabled = ?8, totp_secret = ?9, totp_enabled = ?10, \
+             totp_last_step = ?11, updated_at = ?12 WHERE id = ?1",
+        )
+        .bind(&user.id)
+        .bind(&user.username)
+        .bind(&user.email)
+        .bind(user.email_verified)
+        .bind(&user.password_hash)
+        .bind(roles)
+        .bind(permissions)
+        .bind(user.disabled)
+        .bind(&user.totp_secret)
+        .bind(user.totp_enabled)
+        .bind(user.totp_last_step)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(AuthError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    async fn disable(&self, id: &str) -> AuthResult<()> {
+        self.set_column(id, "disabled", true).await
+    }
+
+    async fn set_password_hash(&self, id: &str, password_hash: &str) -> AuthResult<()> {
+        self.set_column(id, "password_hash", password_hash.to_string()).await
+    }
+
+    async fn mark_email_verified(&self, id: &str) -> AuthResult<()> {
+        self.set_column(id, "email_verified", true).await
+    }
+
+    async fn record_totp_step(&self, id: &str, step: i64) -> AuthResult<bool> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let result = sqlx::query(
+            "UPDATE users SET totp_last_step = ?2, updated_at = ?3 WHERE id = ?1 AND totp_last_step < ?2",
+        )
+        .bind(id)
+        .bind(step)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn advance_magic_link_generation(&self, id: &str, generation: i64) -> AuthResult<bool> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let result = sqlx::query(
+            "UPDATE users SET magic_link_generation = ?2 + 1, updated_at = ?3 \
+             WHERE id = ?1 AND magic_link_generation = ?2",
+        )
+        .bind(id)
+        .bind(generation)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+fn row_to_user(row: &SqliteRow) -> AuthResult<User> {
+    let roles: String = row.try_get("roles").map_err(storage_error)?;
+    let permissions: String = row.try_get("permissions").map_err(storage_error)?;
+    Ok(User {
+        id: row.try_get("id").map_err(storage_error)?,
+        username: row.try_get("username").map_err(storage_error)?,
+        email: row.try_get("email").map_err(storage_error)?,
+        email_verified: row.try_get("email_verified").map_err(storage_error)?,
+        password_hash: row.try_get("password_hash").map_err(storage_error)?,
+        roles: serde_json::from_str(&roles).map_err(storage_error)?,
+        permissions: serde_json::from_str(&permissions).map_err(storage_error)?,
+        disabled: row.try_get("disabled").map_err(storage_error)?,
+        totp_secret: row.try_get("totp_secret").map_err(storage_error)?,
+        totp_enabled: row.try_get("totp_enabled").map_err(storage_error)?,
+        totp_last_step: row.try_get("totp_last_step").map_err(storage_error)?,
+        magic_link_generation: row.try_get("magic_link_generation").map_err(storage_error)?,
+        created_at: row.try_get("created_at").map_err(storage_error)?,
+        updated_at: row.try_get("updated_at").map_err(storage_error)?,
+    })
+}
+
+fn write_error(e: sqlx::Error) -> AuthError {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => AuthError::UserAlreadyExists,
+        _ => storage_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::connect_sqlite;
+
+    async fn repository() -> SqliteUserRepository {
+        let path = std::env::temp_dir().join(format!("users-{}.db", uuid::Uuid::new_v4()));
+        let pool = connect_sqlite(&format!("sqlite://{}", path.display())).await.unwrap();
+        SqliteUserRepository::new(pool)
+    }
+
+    #[actix_web::test]
+    async fn narrow_writes_leave_other_fields_alone() {
+        let repo = repository().await;
+        let user = User::new("ada".into(), "ada@example.com".into(), "old-hash".into(), Vec::new(), Vec::new());
+        let user = repo.create(user).await.unwrap();
+
+        // Each write touches only its own column
+        repo.set_password_hash(&user.id, "new-hash").await.unwrap();
+        repo.mark_email_verified(&user.id).await.unwrap();
+
+        let stored = repo.find_by_id(&user.id).await.unwrap().unwrap();
+        assert_eq!(stored.password_hash, "new-hash");
+        assert!(stored.email_verified);
+        assert!(!stored.disabled);
+
+        repo.disable(&user.id).await.unwrap();
+        let stored = repo.find_by_id(&user.id).await.unwrap().unwrap();
+        assert!(stored.disabled);
+        assert_eq!(stored.password_hash, "new-hash");
+
+        assert!(matches!(repo.disable("missing").await, Err(AuthError::UserNotFound)));
+    }
+
+    #[actix_web::test]
+    async fn totp_step_only_moves_forward() {
+        let repo = repository().await;
+        let user = User::new("ada".into(), "ada@example.com".into(), "hash".into(), Vec::new(), Vec::new());
+        let user = repo.create(user).await.unwrap();
+
+        assert!(repo.record_totp_step(&user.id, 100).await.unwrap());
+        // A replayed code, or an older one still inside the skew window
+        assert!(!repo.record_totp_step(&user.id, 100).await.unwrap());
+        assert!(!repo.record_totp_step(&user.id, 99).await.unwrap());
+        assert!(repo.record_totp_step(&user.id, 101).await.unwrap());
+        assert_eq!(repo.find_by_id(&user.id).await.unwrap().unwrap().totp_last_step, 101);
+    }
+
+    #[actix_web::test]
+    async fn magic_link_generation_advances_once() {
+        let repo = repository().await;
+        let user = User::new("ada".into(), "ada@example.com".into(), "hash".into(), Vec::new(), Vec::new());
+        let user = repo.create(user).await.unwrap();
+
+        assert!(repo.advance_magic_link_generation(&user.id, 0).await.unwrap());
+        assert!(!repo.advance_magic_link_generation(&user.id, 0).await.unwrap());
+
+        // A full update from a stale copy doesn't wind it back
+        repo.update(&user).await.unwrap();
+        assert_eq!(repo.find_by_id(&user.id).await.unwrap().unwrap().magic_link_generation, 1);
+    }
+}